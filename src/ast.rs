@@ -7,43 +7,59 @@ pub enum AstNode {
     Compound(Vec<Rc<RefCell<Ast>>>),
     /// Content
     Stat(String),
+    /// Content of a statement suspending a coroutine, with `co_await` or `co_yield`
+    Suspend(String),
     /// Content
     Continue(String),
     /// Content
     Break(String),
     /// Content
     Return(String),
-    /// Condition, Children1, Children2
+    /// Condition, Children1, Children2, ranges of the parts are apart from the whole statement
     If {
         cond: String,
+        cond_range: Range<usize>,
         body: Rc<RefCell<Ast>>,
         otherwise: Option<Rc<RefCell<Ast>>>,
     },
     /// Condition, Children
     While {
         cond: String,
+        cond_range: Range<usize>,
         body: Rc<RefCell<Ast>>,
     },
     /// Condition, Children
     DoWhile {
         cond: String,
+        cond_range: Range<usize>,
         body: Rc<RefCell<Ast>>,
     },
     /// Init, Condition, Update, Children
     For {
         init: String,
+        init_range: Range<usize>,
         cond: String,
+        cond_range: Range<usize>,
         upd: String,
+        upd_range: Range<usize>,
         body: Rc<RefCell<Ast>>,
     },
     /// Condition, Children, Body
     Switch {
         cond: String,
+        cond_range: Range<usize>,
         cases: Vec<String>,
         body: Rc<RefCell<Ast>>,
     },
     /// Label Name
     Goto(String),
+    /// Body, (Catch Parameter, Handler)
+    Try {
+        body: Rc<RefCell<Ast>>,
+        handlers: Vec<(String, Rc<RefCell<Ast>>)>,
+    },
+    /// Content
+    Throw(String),
 }
 #[derive(Debug)]
 pub struct Ast {
@@ -57,3 +73,16 @@ impl Ast {
         Ast { node, range, label }
     }
 }
+
+/// Value of a condition which is a literal, like `(true)` or `0`
+pub fn literal_condition(text: &str) -> Option<bool> {
+    let mut text = text.trim();
+    while let Some(inner) = text.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+        text = inner.trim();
+    }
+    match text {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::Result;
+
+/// Extensions of the files picked up when walking a directory
+pub const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "c++", "h", "hh", "hpp", "hxx"];
+
+pub fn is_source(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| SOURCE_EXTENSIONS.contains(&x.to_ascii_lowercase().as_str()))
+}
+
+/// Whether `path` is a compilation database rather than a source file
+pub fn is_compile_commands(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|x| x == "compile_commands.json")
+}
+
+/// An entry of `compile_commands.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CompileCommand {
+    /// Working directory of the command, absolute after [`load_compile_commands`]
+    pub directory: PathBuf,
+    /// The source file, absolute after [`load_compile_commands`]
+    pub file: PathBuf,
+    command: Option<String>,
+    arguments: Option<Vec<String>>,
+}
+
+impl CompileCommand {
+    /// The command line, split into arguments if given as a single string
+    pub fn arguments(&self) -> Vec<String> {
+        match (&self.arguments, &self.command) {
+            (Some(arguments), _) => arguments.clone(),
+            (None, Some(command)) => split_command(command),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// Read a compilation database, resolving its paths
+pub fn load_compile_commands(path: &Path) -> Result<Vec<CompileCommand>> {
+    // relative directories are relative to the database
+    let path = std::path::absolute(path)?;
+    let base = path.parent().unwrap_or(Path::new("/"));
+    let mut commands: Vec<CompileCommand> = serde_json::from_slice(&std::fs::read(&path)?)?;
+    for command in &mut commands {
+        command.directory = normalize(&base.join(&command.directory));
+        command.file = normalize(&command.directory.join(&command.file));
+    }
+    Ok(commands)
+}
+
+/// The first command compiling `file`
+pub fn find_command<'a>(commands: &'a [CompileCommand], file: &Path) -> Option<&'a CompileCommand> {
+    let file = normalize(&std::path::absolute(file).ok()?);
+    commands.iter().find(|x| x.file == file)
+}
+
+/// Split a command line the way a POSIX shell does, honoring quotes and backslashes
+fn split_command(command: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(c) = chars.next() {
+                    current.get_or_insert_default().push(c);
+                }
+            }
+            (Some(_), c) => current.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => res.extend(current.take()),
+            (None, c) => current.get_or_insert_default().push(c),
+        }
+    }
+    res.extend(current);
+    res
+}
+
+/// A set of source files to convert, with the directory the output tree mirrors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    pub root: PathBuf,
+    /// sorted, without duplicates
+    pub files: Vec<PathBuf>,
+    /// commands of the compilation database the files come from, if any
+    pub commands: Vec<CompileCommand>,
+}
+
+impl Batch {
+    /// Collect every C/C++ file below the directory `input`, or listed in the
+    /// compilation database `input`.
+    pub fn new(input: &Path) -> Result<Batch> {
+        let (root, mut files, commands) = if input.is_dir() {
+            let mut files = Vec::new();
+            walk(input, &mut files)?;
+            (input.to_path_buf(), files, Vec::new())
+        } else {
+            let commands = load_compile_commands(input)?;
+            let files: Vec<PathBuf> = commands.iter().map(|x| x.file.clone()).collect();
+            // the database usually sits in a build directory, next to the sources
+            (common_ancestor(&files), files, commands)
+        };
+        files.sort();
+        files.dedup();
+        Ok(Batch {
+            root,
+            files,
+            commands,
+        })
+    }
+
+    /// The first command compiling `file`, if the files come from a compilation database
+    pub fn command(&self, file: &Path) -> Option<&CompileCommand> {
+        find_command(&self.commands, file)
+    }
+
+    /// Directory receiving the charts of `file`, e.g. `out/src/main.cpp/` for `src/main.cpp`
+    pub fn output_dir(&self, output_dir: &Path, file: &Path) -> PathBuf {
+        let relative = file.strip_prefix(normalize(&self.root)).unwrap_or(file);
+        output_dir.join(
+            relative
+                .components()
+                .filter(|x| matches!(x, Component::Normal(_)))
+                .collect::<PathBuf>(),
+        )
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // symlinked directories are not followed, they may form cycles
+        if entry.file_type()?.is_dir() {
+            walk(&path, files)?;
+        } else if is_source(&path) {
+            files.push(normalize(&path));
+        }
+    }
+    Ok(())
+}
+
+fn common_ancestor(files: &[PathBuf]) -> PathBuf {
+    let mut res = match files.first() {
+        Some(file) => file.parent().unwrap_or(Path::new("")).to_path_buf(),
+        None => return PathBuf::new(),
+    };
+    for file in files {
+        while !file.starts_with(&res) {
+            if !res.pop() {
+                break;
+            }
+        }
+    }
+    res
+}
+
+/// Drop `.` and resolve `..` lexically, so that the same file always has the same path
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(res.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                res.pop();
+            }
+            _ => res.push(component),
+        }
+    }
+    res
+}
@@ -1,4 +1,5 @@
-use clap::Parser;
+use crate::{LambdaMode, SwitchMode, UnreachableMode, display::Locale};
+use clap::{Parser, Subcommand};
 use std::sync::LazyLock;
 
 static NONE: &str = "None";
@@ -22,13 +23,16 @@ Cargo Target Triple: {}
         env!("VERGEN_CARGO_TARGET_TRIPLE"),
     )
 });
-#[derive(Parser, Debug)]
-#[clap(about, version, long_version(LONG_VERSION.as_str()) ,author, after_help("Note that you need to manually compile the dot file using graphviz to get SVG or PNG files.
+#[derive(Parser, Debug, Clone)]
+#[clap(about, version, long_version(LONG_VERSION.as_str()) ,author, args_conflicts_with_subcommands(true), after_help("Note that you need to manually compile the dot file using graphviz to get PNG files,
+or use --svg to get an SVG file directly.
 
 EXAMPLES:
     cat main.cpp | cxx2flow | dot -Tsvg -o test.svg
     cxx2flow test.cpp | dot -Tpng -o test.png
     cxx2flow main.cpp my_custom_func | dot -Tsvg -o test.svg
+    cxx2flow --svg main.cpp -o test.svg
+    cxx2flow lsp
 
 Please give me star if this application helps you!
 如果这个应用有帮助到你，请给我点一个 star！
@@ -56,27 +60,329 @@ If specified, output flow chart will have curly connection line."
     )]
     pub curly: bool,
 
-    #[clap(long, help("Use C preprocessor."))]
+    #[clap(
+        long,
+        help(
+            "Link every node back to its lines in the input file.
+Only dot and d2 output carry the links."
+        )
+    )]
+    pub link: bool,
+
+    #[clap(
+        long,
+        help(
+            "Use C preprocessor.
+Line markers are followed, so locations still point into the input file."
+        )
+    )]
     pub cpp: bool,
 
+    #[clap(
+        short = 'I',
+        value_name("DIR"),
+        help("Add a directory to the include path of the preprocessor.")
+    )]
+    pub include_dirs: Vec<String>,
+
+    #[clap(
+        short = 'D',
+        value_name("NAME[=VALUE]"),
+        help(
+            "Define a macro for the preprocessor.
+Without --cpp, #if blocks are still decided against the macros given."
+        )
+    )]
+    pub defines: Vec<String>,
+
+    #[clap(
+        short = 'U',
+        value_name("NAME"),
+        help("Undefine a macro for the preprocessor.")
+    )]
+    pub undefines: Vec<String>,
+
+    #[clap(
+        long,
+        value_name("STD"),
+        help("Sets the language standard of the preprocessor, e.g. c++17.")
+    )]
+    pub std: Option<String>,
+
+    #[clap(
+        long,
+        value_name("FILE"),
+        help(
+            "Take the preprocessor flags of the input from a compile_commands.json.
+-I, -D, -U and --std are added after them."
+        )
+    )]
+    pub compile_commands: Option<String>,
+
     #[clap(short, long, help("Use tikz backend."))]
     pub tikz: bool,
 
     #[clap(short, long, help("Use d2 backend."))]
     pub d2: bool,
 
+    #[clap(short, long, help("Use mermaid backend."))]
+    pub mermaid: bool,
+
+    #[clap(
+        short,
+        long,
+        help("Use JSON backend, which carries the source range of every node.")
+    )]
+    pub json: bool,
+
+    #[clap(
+        short,
+        long,
+        help("Use built-in SVG backend, which needs no external renderer.")
+    )]
+    pub svg: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        help(
+            "Sets the language of the words drawn around the code, like begin, end and the branch labels.
+Every backend but JSON draws them the same way."
+        )
+    )]
+    pub locale: Locale,
+
+    #[clap(
+        long,
+        value_name("YES,NO"),
+        value_parser(label_pair),
+        help("Sets the labels of the branches of a decision, e.g. true,false.")
+    )]
+    pub branch_labels: Option<(String, String)>,
+
+    #[clap(
+        long,
+        value_name("BEGIN,END"),
+        value_parser(label_pair),
+        help("Sets the labels of the begin and end of the flow, e.g. Start,Stop.")
+    )]
+    pub terminal_labels: Option<(String, String)>,
+
+    #[clap(long, help("Leaves out the question mark after every condition."))]
+    pub no_question_mark: bool,
+
+    #[clap(
+        long,
+        value_name("NAME|FILE"),
+        help(
+            "Sets the colors, fonts and shapes of the chart, from a TOML theme file
+or one of the built-in themes: monochrome, dark."
+        )
+    )]
+    pub theme: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with("output_dir"),
+        help(
+            "Draw a Nassi-Shneiderman diagram (structogram) as SVG instead of a flowchart.
+Functions using goto cannot be drawn this way."
+        )
+    )]
+    pub structogram: bool,
+
+    #[clap(
+        long,
+        help(
+            "Convert every function in the input file.
+Charts are combined into a single document, unless --output-dir is specified."
+        )
+    )]
+    pub all: bool,
+
+    #[clap(
+        long,
+        help(
+            "Sets the output directory for --all.
+Each function is written to a separate file named after it.
+When the input is a directory, every file gets a directory of its own in the mirrored tree."
+        )
+    )]
+    pub output_dir: Option<String>,
+
+    #[clap(
+        long,
+        help("Only write the functions whose name matches the glob, e.g. 'ns::*'.")
+    )]
+    pub filter: Option<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        conflicts_with("all"),
+        help("Sets how lambda expressions in the function are drawn.")
+    )]
+    pub lambdas: LambdaMode,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        help(
+            "Sets how code which can never run is drawn.
+It is reported as a warning either way."
+        )
+    )]
+    pub unreachable: UnreachableMode,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        help(
+            "Sets how switch statements are drawn.
+multi-way draws a single decision with an edge labeled with the case values to every case."
+        )
+    )]
+    pub switches: SwitchMode,
+
+    #[clap(
+        long,
+        help("Merges statements running one after another into basic blocks.")
+    )]
+    pub basic_blocks: bool,
+
+    #[clap(
+        long,
+        value_name("N"),
+        value_parser(clap::value_parser!(usize)),
+        help("Sets the most lines of code a basic block may take.")
+    )]
+    pub max_block_lines: Option<usize>,
+
+    #[clap(
+        long,
+        value_name("N"),
+        value_parser(clap::value_parser!(usize)),
+        help(
+            "Folds loops, ifs and switches nested deeper than N into a single statement,
+like \"for (...) { 14 statements }\"."
+        )
+    )]
+    pub max_depth: Option<usize>,
+
+    #[clap(
+        long,
+        help("Adds a chart of its own for every piece of code folded by --max-depth.")
+    )]
+    pub fold_charts: bool,
+
+    #[clap(
+        long,
+        help("Keeps only the branch taken of every `if constexpr` with a literal condition.")
+    )]
+    pub prune_constexpr: bool,
+
+    #[clap(
+        long,
+        help("Marks the way on out of every co_await and co_yield as a dashed \"resume\" edge.")
+    )]
+    pub resume_edges: bool,
+
+    #[clap(
+        long,
+        help("Draws an end node of its own after every return, rather than a single shared one.")
+    )]
+    pub separate_returns: bool,
+
+    #[clap(
+        long,
+        help(
+            "Print control flow metrics (e.g. cyclomatic complexity) instead of a chart.
+Combine with --json for a JSON report, and with --all for every function."
+        )
+    )]
+    pub metrics: bool,
+
+    #[clap(
+        long,
+        help(
+            "List the functions that can be converted, with their line ranges and complexity.
+Combine with --json for a JSON listing."
+        )
+    )]
+    pub list: bool,
+
+    #[clap(
+        long,
+        requires("input"),
+        help(
+            "Regenerate the output whenever the input file changes.
+Only outputs whose content changed are rewritten, errors are reported without exiting."
+        )
+    )]
+    pub watch: bool,
+
+    #[clap(
+        long,
+        value_name("FILE"),
+        help(
+            "Read the project configuration from FILE.
+If not specified, the first .cxx2flow.toml in the directory of the input or above it is read.
+Options given on the command line take precedence over the configuration."
+        )
+    )]
+    pub config: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with_all(["config", "profile"]),
+        help("Ignore the project configuration.")
+    )]
+    pub no_config: bool,
+
+    #[clap(
+        long,
+        value_name("NAME"),
+        help("Use the options of a profile of the project configuration, e.g. docs.")
+    )]
+    pub profile: Option<String>,
+
     #[clap(long, help("Dump AST(For debug purpose only)."))]
     pub dump_ast: bool,
 
     #[clap(help(
         "Sets the path of the input file. e.g. test.cpp
-If not specified, cxx2flow will read from stdin."
+If not specified, cxx2flow will read from stdin.
+A directory or compile_commands.json converts every C/C++ file in it, see --output-dir."
     ))]
     pub input: Option<String>,
 
     #[clap(
         default_value("main"),
-        help("The function you want to convert. e.g. main")
+        help(
+            "The function you want to convert. e.g. main, ns::Foo::run
+Add a parameter list to pick an overload, e.g. 'run(int)'."
+        )
     )]
     pub function: String,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Two labels separated by a comma, like `true,false`
+fn label_pair(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once(',')
+        .map(|(first, second)| (first.to_owned(), second.to_owned()))
+        .ok_or_else(|| format!("expected two labels separated by a comma, got {value:?}"))
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Serve flow charts to editors over the Language Server Protocol on stdio.
+    Lsp,
 }
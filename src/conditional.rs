@@ -0,0 +1,434 @@
+//! Conditional compilation without an external preprocessor.
+//!
+//! `#if` blocks are decided against the macros given on the command line and the `#define`s
+//! met on the way, following the rules of the C preprocessor: undefined names evaluate to `0`.
+//! Like a compiler, `__cplusplus` or `__STDC_VERSION__` and the macros of the host platform are
+//! predefined. Macros from included headers are unknown, pass them with `-D` or use `--cpp`.
+
+use std::{collections::HashMap, path::Path};
+
+/// Macros which are defined, with their replacement text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Macros(HashMap<String, String>);
+
+impl Macros {
+    /// Macros of `-D NAME[=VALUE]` and `-U NAME` flags, `NAME` alone stands for `1`
+    pub fn new(defines: &[String], undefines: &[String]) -> Self {
+        Macros::default().with_flags(defines, undefines)
+    }
+
+    /// Add the macros of `-D` flags and remove the ones of `-U` flags
+    pub fn with_flags(mut self, defines: &[String], undefines: &[String]) -> Self {
+        for define in defines {
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            self.define(name, value);
+        }
+        for undefine in undefines {
+            self.0.remove(undefine.trim());
+        }
+        self
+    }
+
+    /// Macros the compiler defines by itself for the language standard `std`, e.g. `c++20` or
+    /// `gnu11`, and the host platform. Without `std`, `file` is C when it ends with `.c` and
+    /// C++17 otherwise.
+    pub fn predefined(std: Option<&str>, file: Option<&Path>) -> Self {
+        let mut res = Macros::default();
+        res.define("__STDC__", "1");
+        let is_c = match std {
+            Some(std) => !std.contains("++"),
+            None => file.is_some_and(|x| x.extension().is_some_and(|x| x == "c")),
+        };
+        let version = std.map(|x| x.trim_start_matches(|c: char| !c.is_ascii_digit()));
+        if is_c {
+            let version = match version.unwrap_or("17") {
+                "89" | "90" | "" => None,
+                "99" | "9x" => Some("199901L"),
+                "11" | "1x" => Some("201112L"),
+                "23" | "2x" => Some("202311L"),
+                _ => Some("201710L"),
+            };
+            if let Some(version) = version {
+                res.define("__STDC_VERSION__", version);
+            }
+        } else {
+            let version = match version.unwrap_or("17") {
+                "98" | "03" => "199711L",
+                "11" | "0x" => "201103L",
+                "14" | "1y" => "201402L",
+                "20" | "2a" => "202002L",
+                "23" | "2b" => "202302L",
+                "26" | "2c" => "202400L",
+                _ => "201703L",
+            };
+            res.define("__cplusplus", version);
+        }
+        for name in PLATFORM_MACROS {
+            res.define(name, "1");
+        }
+        res
+    }
+
+    fn define(&mut self, name: &str, value: &str) {
+        // function-like macros only matter to `defined`
+        let (name, value) = match name.split_once('(') {
+            Some((name, _)) => (name, ""),
+            None => (name, value),
+        };
+        self.0
+            .insert(name.trim().to_owned(), value.trim().to_owned());
+    }
+
+    /// Apply `#define NAME value` or `#define NAME(args) body`
+    fn define_directive(&mut self, text: &str) {
+        let text = text.trim_start();
+        let end = text.find(|c: char| !is_identifier(c)).unwrap_or(text.len());
+        let (name, rest) = text.split_at(end);
+        let value = if rest.starts_with('(') { "" } else { rest };
+        self.0.insert(name.to_owned(), value.trim().to_owned());
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.0.contains_key(name.trim())
+    }
+}
+
+/// Macros of the operating system and architecture cxx2flow runs on
+const PLATFORM_MACROS: &[&str] = &[
+    #[cfg(target_os = "linux")]
+    "__linux__",
+    #[cfg(target_os = "linux")]
+    "__linux",
+    #[cfg(unix)]
+    "__unix__",
+    #[cfg(unix)]
+    "__unix",
+    #[cfg(target_os = "macos")]
+    "__APPLE__",
+    #[cfg(target_os = "macos")]
+    "__MACH__",
+    #[cfg(target_os = "freebsd")]
+    "__FreeBSD__",
+    #[cfg(windows)]
+    "_WIN32",
+    #[cfg(all(windows, target_pointer_width = "64"))]
+    "_WIN64",
+    #[cfg(target_arch = "x86_64")]
+    "__x86_64__",
+    #[cfg(target_arch = "x86")]
+    "__i386__",
+    #[cfg(target_arch = "aarch64")]
+    "__aarch64__",
+];
+
+fn is_identifier(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+/// Blank out directive lines, including their continuation lines, and the lines of the
+/// branches which are not compiled under `macros`. Positions in the result match the input.
+pub fn blank_directives(content: &str, macros: &Macros) -> String {
+    let mut macros = macros.clone();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut res: Vec<&str> = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let active = blocks.last().is_none_or(|x| x.active);
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            res.push(if active { line } else { "" });
+            continue;
+        };
+        res.push("");
+        let mut directive = directive.to_owned();
+        while directive.ends_with('\\') {
+            directive.pop();
+            let Some(next) = lines.next() else {
+                break;
+            };
+            directive.push_str(next);
+            res.push("");
+        }
+        let directive = strip_comments(&directive);
+        let directive = directive.trim_start();
+        let end = directive
+            .find(|c: char| !is_identifier(c))
+            .unwrap_or(directive.len());
+        let (name, rest) = directive.split_at(end);
+        let condition = || match name {
+            "if" | "elif" => evaluate(rest, &macros) != 0,
+            "ifdef" | "elifdef" => macros.is_defined(rest),
+            "ifndef" | "elifndef" => !macros.is_defined(rest),
+            _ => true,
+        };
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let taken = active && condition();
+                blocks.push(Block {
+                    outer: active,
+                    taken,
+                    active: taken,
+                });
+            }
+            "elif" | "elifdef" | "elifndef" | "else" => {
+                if let Some(block) = blocks.last() {
+                    let active = block.outer && !block.taken && condition();
+                    let block = blocks.last_mut().unwrap();
+                    block.active = active;
+                    block.taken |= active;
+                }
+            }
+            "endif" => {
+                blocks.pop();
+            }
+            "define" if active => macros.define_directive(rest),
+            "undef" if active => {
+                macros.0.remove(rest.trim());
+            }
+            _ => {}
+        }
+    }
+    res.join("\n")
+}
+
+/// A conditional block being read
+struct Block {
+    /// whether the lines around the block are compiled
+    outer: bool,
+    /// whether one of the branches read so far is compiled
+    taken: bool,
+    /// whether the current branch is compiled
+    active: bool,
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('/') {
+        res.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if tail.starts_with("//") {
+            return res;
+        } else if let Some(comment) = tail.strip_prefix("/*") {
+            res.push(' ');
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+        } else {
+            res.push('/');
+            rest = &tail[1..];
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Punct(&'static str),
+    Other(char),
+}
+
+const PUNCTS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "!", "~", "-", "+", "*", "/", "%", "<", ">",
+    "&", "^", "|", "?", ":", "(", ")", ",",
+];
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut res = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            // digit separators and suffixes are part of the number
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '.'))
+                .unwrap_or(rest.len());
+            res.push(Token::Number(parse_number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if is_identifier(c) {
+            let end = rest.find(|c| !is_identifier(c)).unwrap_or(rest.len());
+            res.push(Token::Identifier(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else if let Some(literal) = rest.strip_prefix('\'') {
+            let mut chars = literal.chars();
+            let value = chars.next().filter(|&x| x != '\\')?;
+            rest = chars.as_str().strip_prefix('\'')?;
+            res.push(Token::Number(value as i64));
+        } else if let Some(punct) = PUNCTS.iter().find(|x| rest.starts_with(**x)) {
+            res.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else {
+            res.push(Token::Other(c));
+            rest = &rest[c.len_utf8()..];
+        }
+        rest = rest.trim_start();
+    }
+    Some(res)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.replace('\'', "").to_ascii_lowercase();
+    let text = text.trim_end_matches(['u', 'l', 'z']);
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        (binary, 2)
+    } else if text.len() > 1
+        && let Some(octal) = text.strip_prefix('0')
+    {
+        (octal, 8)
+    } else {
+        (text, 10)
+    };
+    u64::from_str_radix(digits, radix).ok().map(|x| x as i64)
+}
+
+/// Value of the expression of an `#if`, conditions which cannot be evaluated are false
+fn evaluate(text: &str, macros: &Macros) -> i64 {
+    evaluate_nested(text, macros, 0).unwrap_or(0)
+}
+
+/// Macros expanding to other macros are followed this deep
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+fn evaluate_nested(text: &str, macros: &Macros, depth: usize) -> Option<i64> {
+    if depth > MAX_EXPANSION_DEPTH {
+        return None;
+    }
+    let tokens = tokenize(text)?;
+    let mut parser = ExpressionParser {
+        tokens: &tokens,
+        position: 0,
+        macros,
+        depth,
+    };
+    let res = parser.conditional()?;
+    (parser.position == tokens.len()).then_some(res)
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    macros: &'a Macros,
+    depth: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let res = self.tokens.get(self.position);
+        self.position += 1;
+        res
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let res = matches!(self.tokens.get(self.position), Some(Token::Punct(x)) if *x == punct);
+        if res {
+            self.position += 1;
+        }
+        res
+    }
+
+    fn conditional(&mut self) -> Option<i64> {
+        let condition = self.binary(1)?;
+        if !self.eat("?") {
+            return Some(condition);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let otherwise = self.conditional()?;
+        Some(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Option<i64> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Punct(op)) = self.tokens.get(self.position)
+            && let Some(precedence) = precedence(op)
+            && precedence >= min_precedence
+        {
+            self.position += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = match *op {
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "&" => lhs & rhs,
+                "^" => lhs ^ rhs,
+                "|" => lhs | rhs,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                _ => unreachable!("{op} has a precedence"),
+            };
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        match self.next()?.clone() {
+            Token::Number(value) => Some(value),
+            Token::Punct("!") => Some((self.unary()? == 0) as i64),
+            Token::Punct("~") => Some(!self.unary()?),
+            Token::Punct("-") => Some(self.unary()?.wrapping_neg()),
+            Token::Punct("+") => self.unary(),
+            Token::Punct("(") => {
+                let res = self.conditional()?;
+                self.eat(")").then_some(res)
+            }
+            Token::Identifier(name) if name == "defined" => {
+                let parenthesized = self.eat("(");
+                let Some(Token::Identifier(name)) = self.next().cloned() else {
+                    return None;
+                };
+                let res = self.macros.is_defined(&name) as i64;
+                (!parenthesized || self.eat(")")).then_some(res)
+            }
+            Token::Identifier(name) => self.identifier(&name),
+            Token::Punct(_) | Token::Other(_) => None,
+        }
+    }
+
+    fn identifier(&mut self, name: &str) -> Option<i64> {
+        // calls of function-like macros and of `__has_include` and friends are unknown
+        if self.eat("(") {
+            let mut depth = 1;
+            while depth > 0 {
+                match self.next()? {
+                    Token::Punct("(") => depth += 1,
+                    Token::Punct(")") => depth -= 1,
+                    _ => {}
+                }
+            }
+            return Some(0);
+        }
+        match self.macros.0.get(name) {
+            Some(value) => evaluate_nested(value, self.macros, self.depth + 1),
+            None => Some((name == "true") as i64),
+        }
+    }
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | "<=" | ">" | ">=" => 7,
+        "==" | "!=" => 6,
+        "&" => 5,
+        "^" => 4,
+        "|" => 3,
+        "&&" => 2,
+        "||" => 1,
+        _ => return None,
+    })
+}
@@ -0,0 +1,568 @@
+//! Project configuration, giving the options of the command line defaults for a whole project.
+//!
+//! The configuration is read from the first `.cxx2flow.toml` in the directory of the input or
+//! above it. Options are named like on the command line, without the leading dashes, and those
+//! given on the command line still take precedence:
+//!
+//! ```toml
+//! [options]
+//! curly = true
+//! svg = true
+//!
+//! [preprocessor]
+//! cpp = true
+//! include-dirs = ["include"]
+//! std = "c++17"
+//!
+//! [profiles.docs]
+//! d2 = true
+//! theme = "dark"
+//!
+//! [[overrides]]
+//! files = "legacy/**/*.c"
+//! options = { switches = "multi-way" }
+//! preprocessor = { defines = ["LEGACY"] }
+//! ```
+//!
+//! Paths in the configuration are relative to the directory it is in.
+
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::{ArgMatches, parser::ValueSource};
+use glob::{MatchOptions, Pattern};
+use miette::{NamedSource, SourceSpan};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    LambdaMode, SwitchMode, UnreachableMode,
+    batch::normalize,
+    cli::Args,
+    display::{Locale, theme::BUILTIN_THEMES},
+    error::{Error, Result},
+};
+
+/// Name of the configuration file
+pub const FILE_NAME: &str = ".cxx2flow.toml";
+
+/// Options of the command line, besides those of the preprocessor.
+/// Every field left out keeps the value of the layer below.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    pub output: Option<String>,
+    pub curly: Option<bool>,
+    pub link: Option<bool>,
+    pub tikz: Option<bool>,
+    pub d2: Option<bool>,
+    pub mermaid: Option<bool>,
+    pub json: Option<bool>,
+    pub svg: Option<bool>,
+    pub locale: Option<Locale>,
+    pub branch_labels: Option<(String, String)>,
+    pub terminal_labels: Option<(String, String)>,
+    pub no_question_mark: Option<bool>,
+    pub theme: Option<String>,
+    pub structogram: Option<bool>,
+    pub all: Option<bool>,
+    pub output_dir: Option<String>,
+    pub filter: Option<String>,
+    pub lambdas: Option<LambdaMode>,
+    pub unreachable: Option<UnreachableMode>,
+    pub switches: Option<SwitchMode>,
+    pub basic_blocks: Option<bool>,
+    pub max_block_lines: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub fold_charts: Option<bool>,
+    pub prune_constexpr: Option<bool>,
+    pub resume_edges: Option<bool>,
+    pub separate_returns: Option<bool>,
+    pub metrics: Option<bool>,
+    pub list: Option<bool>,
+    pub watch: Option<bool>,
+    pub dump_ast: Option<bool>,
+}
+
+/// Ids of the flags picking a backend
+const BACKENDS: &[&str] = &["tikz", "d2", "mermaid", "json", "svg"];
+
+impl Options {
+    /// Whether a backend is picked, which then replaces the one of the layer below as a whole
+    fn picks_backend(&self) -> bool {
+        [self.tikz, self.d2, self.mermaid, self.json, self.svg]
+            .iter()
+            .any(Option::is_some)
+    }
+
+    fn without_backend(self) -> Options {
+        Options {
+            tikz: None,
+            d2: None,
+            mermaid: None,
+            json: None,
+            svg: None,
+            ..self
+        }
+    }
+
+    /// Fields of `self`, falling back to those of `lower`
+    fn or(self, lower: Options) -> Options {
+        let lower = if self.picks_backend() {
+            lower.without_backend()
+        } else {
+            lower
+        };
+        Options {
+            output: self.output.or(lower.output),
+            curly: self.curly.or(lower.curly),
+            link: self.link.or(lower.link),
+            tikz: self.tikz.or(lower.tikz),
+            d2: self.d2.or(lower.d2),
+            mermaid: self.mermaid.or(lower.mermaid),
+            json: self.json.or(lower.json),
+            svg: self.svg.or(lower.svg),
+            locale: self.locale.or(lower.locale),
+            branch_labels: self.branch_labels.or(lower.branch_labels),
+            terminal_labels: self.terminal_labels.or(lower.terminal_labels),
+            no_question_mark: self.no_question_mark.or(lower.no_question_mark),
+            theme: self.theme.or(lower.theme),
+            structogram: self.structogram.or(lower.structogram),
+            all: self.all.or(lower.all),
+            output_dir: self.output_dir.or(lower.output_dir),
+            filter: self.filter.or(lower.filter),
+            lambdas: self.lambdas.or(lower.lambdas),
+            unreachable: self.unreachable.or(lower.unreachable),
+            switches: self.switches.or(lower.switches),
+            basic_blocks: self.basic_blocks.or(lower.basic_blocks),
+            max_block_lines: self.max_block_lines.or(lower.max_block_lines),
+            max_depth: self.max_depth.or(lower.max_depth),
+            fold_charts: self.fold_charts.or(lower.fold_charts),
+            prune_constexpr: self.prune_constexpr.or(lower.prune_constexpr),
+            resume_edges: self.resume_edges.or(lower.resume_edges),
+            separate_returns: self.separate_returns.or(lower.separate_returns),
+            metrics: self.metrics.or(lower.metrics),
+            list: self.list.or(lower.list),
+            watch: self.watch.or(lower.watch),
+            dump_ast: self.dump_ast.or(lower.dump_ast),
+        }
+    }
+
+    /// Set the fields of `args` which were not given on the command line
+    fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        let options = if BACKENDS.iter().any(|id| given(matches, id)) {
+            self.without_backend()
+        } else {
+            self
+        };
+        set(
+            matches,
+            "output",
+            &mut args.output,
+            options.output.map(Some),
+        );
+        set(matches, "curly", &mut args.curly, options.curly);
+        set(matches, "link", &mut args.link, options.link);
+        set(matches, "tikz", &mut args.tikz, options.tikz);
+        set(matches, "d2", &mut args.d2, options.d2);
+        set(matches, "mermaid", &mut args.mermaid, options.mermaid);
+        set(matches, "json", &mut args.json, options.json);
+        set(matches, "svg", &mut args.svg, options.svg);
+        set(matches, "locale", &mut args.locale, options.locale);
+        set(
+            matches,
+            "branch_labels",
+            &mut args.branch_labels,
+            options.branch_labels.map(Some),
+        );
+        set(
+            matches,
+            "terminal_labels",
+            &mut args.terminal_labels,
+            options.terminal_labels.map(Some),
+        );
+        set(
+            matches,
+            "no_question_mark",
+            &mut args.no_question_mark,
+            options.no_question_mark,
+        );
+        set(matches, "theme", &mut args.theme, options.theme.map(Some));
+        set(
+            matches,
+            "structogram",
+            &mut args.structogram,
+            options.structogram,
+        );
+        set(matches, "all", &mut args.all, options.all);
+        set(
+            matches,
+            "output_dir",
+            &mut args.output_dir,
+            options.output_dir.map(Some),
+        );
+        set(
+            matches,
+            "filter",
+            &mut args.filter,
+            options.filter.map(Some),
+        );
+        set(matches, "lambdas", &mut args.lambdas, options.lambdas);
+        set(
+            matches,
+            "unreachable",
+            &mut args.unreachable,
+            options.unreachable,
+        );
+        set(matches, "switches", &mut args.switches, options.switches);
+        set(
+            matches,
+            "basic_blocks",
+            &mut args.basic_blocks,
+            options.basic_blocks,
+        );
+        set(
+            matches,
+            "max_block_lines",
+            &mut args.max_block_lines,
+            options.max_block_lines.map(Some),
+        );
+        set(
+            matches,
+            "max_depth",
+            &mut args.max_depth,
+            options.max_depth.map(Some),
+        );
+        set(
+            matches,
+            "fold_charts",
+            &mut args.fold_charts,
+            options.fold_charts,
+        );
+        set(
+            matches,
+            "prune_constexpr",
+            &mut args.prune_constexpr,
+            options.prune_constexpr,
+        );
+        set(
+            matches,
+            "resume_edges",
+            &mut args.resume_edges,
+            options.resume_edges,
+        );
+        set(
+            matches,
+            "separate_returns",
+            &mut args.separate_returns,
+            options.separate_returns,
+        );
+        set(matches, "metrics", &mut args.metrics, options.metrics);
+        set(matches, "list", &mut args.list, options.list);
+        set(matches, "watch", &mut args.watch, options.watch);
+        set(matches, "dump_ast", &mut args.dump_ast, options.dump_ast);
+    }
+
+    /// Make the paths relative to `dir` absolute. Built-in themes are names rather than paths.
+    fn resolve(&mut self, dir: &Path) {
+        for path in [&mut self.output, &mut self.output_dir] {
+            *path = path.as_deref().map(|x| resolve(dir, x));
+        }
+        if let Some(theme) = &mut self.theme
+            && !BUILTIN_THEMES.iter().any(|(name, _)| name == theme)
+        {
+            *theme = resolve(dir, theme);
+        }
+    }
+}
+
+/// Options of the preprocessor, from the command line
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preprocessor {
+    pub cpp: Option<bool>,
+    /// Come before the directories of the layers above, the command line last
+    pub include_dirs: Vec<String>,
+    /// Come before the macros of the layers above, the command line last
+    pub defines: Vec<String>,
+    /// Come before the macros of the layers above, the command line last
+    pub undefines: Vec<String>,
+    pub std: Option<String>,
+    pub compile_commands: Option<String>,
+}
+
+impl Preprocessor {
+    /// Fields of `self`, falling back to those of `lower`, with the lists of both
+    fn or(self, lower: Preprocessor) -> Preprocessor {
+        Preprocessor {
+            cpp: self.cpp.or(lower.cpp),
+            include_dirs: [lower.include_dirs, self.include_dirs].concat(),
+            defines: [lower.defines, self.defines].concat(),
+            undefines: [lower.undefines, self.undefines].concat(),
+            std: self.std.or(lower.std),
+            compile_commands: self.compile_commands.or(lower.compile_commands),
+        }
+    }
+
+    /// Set the fields of `args` which were not given on the command line,
+    /// putting the lists before those of the command line
+    fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        set(matches, "cpp", &mut args.cpp, self.cpp);
+        args.include_dirs = [self.include_dirs, std::mem::take(&mut args.include_dirs)].concat();
+        args.defines = [self.defines, std::mem::take(&mut args.defines)].concat();
+        args.undefines = [self.undefines, std::mem::take(&mut args.undefines)].concat();
+        set(matches, "std", &mut args.std, self.std.map(Some));
+        set(
+            matches,
+            "compile_commands",
+            &mut args.compile_commands,
+            self.compile_commands.map(Some),
+        );
+    }
+
+    /// Make the paths relative to `dir` absolute
+    fn resolve(&mut self, dir: &Path) {
+        for path in &mut self.include_dirs {
+            *path = resolve(dir, path);
+        }
+        self.compile_commands = self.compile_commands.as_deref().map(|x| resolve(dir, x));
+    }
+}
+
+/// Options for the files matching a glob
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Override {
+    /// Glob of the paths relative to the configuration, `*` stops at `/` while `**` does not
+    pub files: Spanned<String>,
+    #[serde(default)]
+    pub options: Options,
+    #[serde(default)]
+    pub preprocessor: Preprocessor,
+}
+
+impl Override {
+    /// Whether the absolute `path` is one of the files of the override
+    fn matches(&self, dir: &Path, path: &Path) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let path = path.strip_prefix(dir).unwrap_or(path);
+        Pattern::new(self.files.get_ref()).is_ok_and(|x| x.matches_path_with(path, options))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub options: Options,
+    pub preprocessor: Preprocessor,
+    /// Options picked with `--profile`, e.g. the backend and theme of the charts of a manual
+    pub profiles: BTreeMap<String, Options>,
+    /// Applied in order to the files they match, after `options` and before the profile
+    pub overrides: Vec<Override>,
+    /// The file the configuration was read from
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Config {
+    /// Read the configuration in the file at `path`, making its paths absolute
+    pub fn load(path: &Path) -> Result<Config> {
+        let content = std::fs::read_to_string(path)?;
+        let invalid = |message: String, range: Option<Range<usize>>| Error::InvalidConfig {
+            src: Arc::new(NamedSource::new(path.to_string_lossy(), content.clone())),
+            range: range.map(SourceSpan::from),
+            message,
+        };
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|error| invalid(error.message().to_owned(), error.span()))?;
+        for x in &config.overrides {
+            if let Err(error) = Pattern::new(x.files.get_ref()) {
+                return Err(invalid(error.msg.to_owned(), Some(x.files.span())));
+            }
+        }
+        config.path = normalize(&std::path::absolute(path)?);
+        let dir = config.dir().to_path_buf();
+        config.options.resolve(&dir);
+        config.preprocessor.resolve(&dir);
+        for options in config.profiles.values_mut() {
+            options.resolve(&dir);
+        }
+        for x in &mut config.overrides {
+            x.options.resolve(&dir);
+            x.preprocessor.resolve(&dir);
+        }
+        Ok(config)
+    }
+
+    /// The configuration in `dir` or the nearest directory above it
+    pub fn find(dir: &Path) -> Result<Option<Config>> {
+        normalize(&std::path::absolute(dir)?)
+            .ancestors()
+            .map(|x| x.join(FILE_NAME))
+            .find(|x| x.is_file())
+            .map(|x| Config::load(&x))
+            .transpose()
+    }
+
+    /// The directory the configuration is in, which its paths are relative to
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("/"))
+    }
+
+    /// Set the options of `args` which were not given on the command line, as they are for `file`
+    /// with the profile `profile`
+    pub fn apply(
+        &self,
+        args: &mut Args,
+        matches: &ArgMatches,
+        file: Option<&Path>,
+        profile: Option<&str>,
+    ) -> Result<()> {
+        let mut options = self.options.clone();
+        let mut preprocessor = self.preprocessor.clone();
+        let file = file
+            .map(std::path::absolute)
+            .transpose()?
+            .map(|x| normalize(&x));
+        for x in &self.overrides {
+            if file
+                .as_ref()
+                .is_some_and(|file| x.matches(self.dir(), file))
+            {
+                options = x.options.clone().or(options);
+                preprocessor = x.preprocessor.clone().or(preprocessor);
+            }
+        }
+        if let Some(name) = profile {
+            let profile = self
+                .profiles
+                .get(name)
+                .ok_or_else(|| Error::ProfileNotFound {
+                    name: name.to_owned(),
+                    file: self.path.to_string_lossy().into_owned(),
+                    profiles: self
+                        .profiles
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                })?;
+            options = profile.clone().or(options);
+        }
+        options.apply(args, matches);
+        preprocessor.apply(args, matches);
+        Ok(())
+    }
+}
+
+/// The command line, with the configuration of the project it is run in
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// As given on the command line
+    pub args: Args,
+    matches: ArgMatches,
+    pub config: Option<Config>,
+}
+
+impl Settings {
+    /// Find the configuration of the input of `args`, or read the one given with `--config`
+    pub fn new(args: Args, matches: ArgMatches) -> Result<Settings> {
+        let config = if args.no_config {
+            None
+        } else if let Some(path) = &args.config {
+            Some(Config::load(Path::new(path))?)
+        } else {
+            let input = args.input.as_deref().map(Path::new);
+            let dir = match input {
+                Some(input) if input.is_dir() => input,
+                Some(input) => input.parent().unwrap_or(Path::new(".")),
+                None => Path::new("."),
+            };
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            Config::find(dir)?
+        };
+        if let (None, Some(name)) = (&config, &args.profile) {
+            return Err(Error::ConfigNotFound { name: name.clone() });
+        }
+        Ok(Settings {
+            args,
+            matches,
+            config,
+        })
+    }
+
+    /// The arguments for `file`, with the options not given on the command line taken from the
+    /// configuration
+    pub fn args(&self, file: Option<&Path>) -> Result<Args> {
+        let mut args = self.args.clone();
+        if let Some(config) = &self.config {
+            config.apply(&mut args, &self.matches, file, self.args.profile.as_deref())?;
+        }
+        check(&args)?;
+        Ok(args)
+    }
+}
+
+/// Options only taking effect along with another one, checked once merged, as either may come
+/// from the configuration
+fn check(args: &Args) -> Result<()> {
+    let requirements = [
+        (!args.include_dirs.is_empty(), "-I", "--cpp", args.cpp),
+        (args.std.is_some(), "--std", "--cpp", args.cpp),
+        (args.output_dir.is_some(), "--output-dir", "--all", args.all),
+        (
+            args.filter.is_some(),
+            "--filter",
+            "--output-dir",
+            args.output_dir.is_some(),
+        ),
+        (
+            args.max_block_lines.is_some(),
+            "--max-block-lines",
+            "--basic-blocks",
+            args.basic_blocks,
+        ),
+        (
+            args.fold_charts,
+            "--fold-charts",
+            "--max-depth",
+            args.max_depth.is_some(),
+        ),
+    ];
+    match requirements
+        .into_iter()
+        .find(|(given, _, _, present)| *given && !*present)
+    {
+        Some((_, option, required, _)) => Err(Error::MissingOption { option, required }),
+        None => Ok(()),
+    }
+}
+
+/// Whether the argument `id` was given on the command line, rather than left at its default
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Set `arg` to `value`, unless it was given on the command line
+fn set<T>(matches: &ArgMatches, id: &str, arg: &mut T, value: Option<T>) {
+    if let Some(value) = value
+        && !given(matches, id)
+    {
+        *arg = value;
+    }
+}
+
+fn resolve(dir: &Path, path: &str) -> String {
+    normalize(&dir.join(path)).to_string_lossy().into_owned()
+}
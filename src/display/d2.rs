@@ -1,64 +1,227 @@
 use crate::error::{Error, Result};
-use crate::graph::{Graph, GraphNodeType};
+use crate::graph::{EdgeType, Graph, GraphNode, GraphNodeType};
 use petgraph::{
     visit::IntoNodeReferences,
     visit::{EdgeRef, IntoEdgeReferences},
 };
 
-use super::GraphDisplay;
+use super::theme::{Shape, Style};
+use super::{GraphDisplay, RenderOptions};
 #[derive(Debug, Default)]
-pub struct D2 {}
+pub struct D2 {
+    /// file the nodes link back to
+    link: Option<String>,
+    options: RenderOptions,
+}
 
 impl D2 {
     pub fn new() -> Self {
-        D2 {}
+        D2 {
+            link: None,
+            options: RenderOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Link every node to its lines in `file_name`, with the position as tooltip
+    pub fn with_link(mut self, file_name: &str) -> Self {
+        self.link = Some(file_name.to_owned());
+        self
+    }
+
+    fn write_link(&self, id: usize, node: &GraphNode, res: &mut String) {
+        if let (Some(file_name), Some(span)) = (&self.link, &node.span) {
+            let file_name = file_name.replace('\"', "\\\"");
+            res.push_str(format!("D{}.link: \"{}#{}\"\n", id, file_name, span.anchor()).as_str());
+            res.push_str(
+                format!(
+                    "D{}.tooltip: \"{}:{}:{}\"\n",
+                    id, file_name, span.start.line, span.start.column
+                )
+                .as_str(),
+            );
+        }
     }
 }
 
-impl GraphDisplay for D2 {
-    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
-        let mut res = String::new();
+/// `style.fill: "#ffffff"` and the like, for the colors, width and dashes of `style`
+fn theme_styles(style: &Style) -> Vec<String> {
+    let mut res = Vec::new();
+    if let Some(fill) = &style.fill {
+        res.push(format!("style.fill: \"{}\"", fill.hex()));
+    }
+    if let Some(stroke) = &style.stroke {
+        res.push(format!("style.stroke: \"{}\"", stroke.hex()));
+    }
+    if let Some(text) = &style.text {
+        res.push(format!("style.font-color: \"{}\"", text.hex()));
+    }
+    // d2 only takes whole widths
+    if let Some(width) = style.width {
+        res.push(format!("style.stroke-width: {}", width.round().max(1.0)));
+    }
+    if style.dashed {
+        res.push("style.stroke-dash: 3".to_owned());
+    }
+    res
+}
+
+impl D2 {
+    /// Styles of the whole document, set on every node and edge in it
+    fn write_theme(&self, res: &mut String) {
+        let theme = &self.options.theme;
+        if let Some(background) = &theme.background {
+            res.push_str(format!("style.fill: \"{}\"\n", background.hex()).as_str());
+        }
+        let mut font = Vec::new();
+        // d2 draws a single font apart from its own, the monospaced one
+        if theme
+            .font
+            .family
+            .as_ref()
+            .is_some_and(|x| x.to_lowercase().contains("mono"))
+        {
+            font.push("style.font: mono".to_owned());
+        }
+        if let Some(size) = theme.font.size {
+            font.push(format!("style.font-size: {}", size.round()));
+        }
+        if let Some(text) = &theme.font.color {
+            font.push(format!("style.font-color: \"{}\"", text.hex()));
+        }
+        for i in font {
+            res.push_str(format!("**.{i}\n(** -> **)[*].{i}\n").as_str());
+        }
+    }
+
+    fn write_graph(&self, graph: &Graph, res: &mut String) -> Result<()> {
         for (id, i) in graph.node_references() {
-            match i {
-                GraphNodeType::Begin => res.push_str(format!("D{}: begin\n", id.index()).as_str()),
-                GraphNodeType::End => res.push_str(format!("D{}: end\n", id.index()).as_str()),
-                GraphNodeType::Node(str) => res.push_str(
-                    format!(
-                        "D{}: \"{}\"\n",
-                        id.index(),
-                        str.replace('\"', "\\\"").replace('\n', "\\n")
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::Choice(str) => {
-                    res.push_str(
-                        format!("D{}: \"{}\"\n", id.index(), str.replace('\"', "\\\"")).as_str(),
-                    );
-                    res.push_str(format!("D{}.shape: diamond\n", id.index()).as_str());
-                }
+            let label = match &i.node_type {
+                GraphNodeType::Begin => self.options.begin.clone(),
+                GraphNodeType::End => self.options.end.clone(),
+                GraphNodeType::Node(str)
+                | GraphNodeType::Suspend(str)
+                | GraphNodeType::Return(str)
+                | GraphNodeType::Jump(str) => str.clone(),
+                GraphNodeType::Block(v) => v.join("\n"),
+                GraphNodeType::Choice(str) | GraphNodeType::Loop(str) => self.options.choice(str),
                 GraphNodeType::Dummy => {
                     return Err(Error::UnexpectedDummyGraphNode {
                         graph: graph.clone(),
                     });
                 }
+            };
+            res.push_str(
+                format!(
+                    "D{}: \"{}\"\n",
+                    id.index(),
+                    label.replace('\"', "\\\"").replace('\n', "\\n")
+                )
+                .as_str(),
+            );
+            let style = self.options.theme.node(&i.node_type);
+            let mut lines = match (style.shape, &i.node_type) {
+                (None, GraphNodeType::Suspend(_)) | (Some(Shape::Hexagon), _) => {
+                    vec!["shape: hexagon".to_owned()]
+                }
+                (None, GraphNodeType::Choice(_) | GraphNodeType::Loop(_))
+                | (Some(Shape::Diamond), _) => {
+                    vec!["shape: diamond".to_owned()]
+                }
+                (None, GraphNodeType::Return(_)) | (Some(Shape::Rounded), _) => vec![
+                    "shape: rectangle".to_owned(),
+                    "style.border-radius: 20".to_owned(),
+                ],
+                (None, GraphNodeType::Jump(_)) | (Some(Shape::Ellipse), _) => {
+                    vec!["shape: oval".to_owned()]
+                }
+                (None, _) => vec![],
+                (Some(Shape::Rectangle), _) => vec!["shape: rectangle".to_owned()],
+            };
+            if let GraphNodeType::Block(_) = i.node_type {
+                lines.push("style.font: mono".to_owned());
+            }
+            lines.extend(theme_styles(&style));
+            for line in lines {
+                res.push_str(format!("D{}.{}\n", id.index(), line).as_str());
+            }
+            self.write_link(id.index(), i, res);
+            if i.unreachable {
+                res.push_str(format!("D{}.style.stroke-dash: 3\n", id.index()).as_str());
+                res.push_str(format!("D{}.style.opacity: 0.5\n", id.index()).as_str());
             }
         }
         for i in graph.edge_references() {
-            match i.weight() {
-                crate::graph::EdgeType::Normal => res.push_str(
-                    format!("D{} -> D{}\n", i.source().index(), i.target().index()).as_str(),
-                ),
-                crate::graph::EdgeType::Branch(t) => res.push_str(
-                    format!(
-                        "D{} -> D{}: {}\n",
-                        i.source().index(),
-                        i.target().index(),
-                        if *t { "Y" } else { "N" }
-                    )
-                    .as_str(),
+            let quote = |x: &str| format!(": \"{}\"", x.replace('\"', "\\\""));
+            let (label, mut styles) = match i.weight() {
+                EdgeType::Normal => (String::new(), vec![]),
+                EdgeType::Branch(t) => (quote(self.options.branch(*t)), vec![]),
+                EdgeType::Exception(param) => {
+                    (quote(param), vec!["style.stroke-dash: 3".to_owned()])
+                }
+                EdgeType::Lambda(name) => (quote(name), vec!["style.stroke-dash: 1".to_owned()]),
+                EdgeType::Case(values) => (quote(values), vec![]),
+                EdgeType::Resume => (
+                    ": resume".to_owned(),
+                    vec!["style.stroke-dash: 3".to_owned()],
                 ),
             };
+            let style = self.options.theme.edge(i.weight());
+            let dashed = styles.iter().any(|x| x.starts_with("style.stroke-dash"));
+            styles.extend(
+                theme_styles(&style)
+                    .into_iter()
+                    .filter(|x| !(dashed && x.starts_with("style.stroke-dash"))),
+            );
+            let styles = if styles.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "{} {{{}}}",
+                    if label.is_empty() { ":" } else { "" },
+                    styles.join("; ")
+                )
+            };
+            res.push_str(
+                format!(
+                    "D{} -> D{}{}{}\n",
+                    i.source().index(),
+                    i.target().index(),
+                    label,
+                    styles
+                )
+                .as_str(),
+            );
         }
+        Ok(())
+    }
+}
+
+impl GraphDisplay for D2 {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let mut res = String::new();
+        self.write_theme(&mut res);
+        self.write_graph(graph, &mut res)?;
         Ok(res)
     }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        // node ids are scoped by the container, so they can be reused
+        let mut res = String::new();
+        self.write_theme(&mut res);
+        for (idx, (name, graph)) in graphs.iter().enumerate() {
+            res.push_str(format!("F{}: \"{}\" {{\n", idx, name.replace('\"', "\\\"")).as_str());
+            self.write_graph(graph, &mut res)?;
+            res.push_str("}\n");
+        }
+        Ok(res)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "d2"
+    }
 }
@@ -1,86 +1,272 @@
 use crate::error::{Error, Result};
-use crate::graph::{Graph, GraphNodeType};
+use crate::graph::{EdgeType, Graph, GraphNode, GraphNodeType};
 use petgraph::{
     visit::IntoNodeReferences,
     visit::{EdgeRef, IntoEdgeReferences},
 };
 
-use super::GraphDisplay;
+use super::theme::{Color, Shape, Style};
+use super::{GraphDisplay, RenderOptions};
+
+fn color(color: &Color) -> String {
+    format!("\"{}\"", color.hex())
+}
+
+/// Shape of a node, with the style flags it needs
+fn shape(node_type: &GraphNodeType, style: &Style) -> (&'static str, Vec<&'static str>) {
+    let shape = style.shape.unwrap_or(match node_type {
+        GraphNodeType::Begin | GraphNodeType::End | GraphNodeType::Return(_) => Shape::Rounded,
+        GraphNodeType::Choice(_) | GraphNodeType::Loop(_) => Shape::Diamond,
+        GraphNodeType::Suspend(_) => Shape::Hexagon,
+        GraphNodeType::Jump(_) => Shape::Ellipse,
+        _ => Shape::Rectangle,
+    });
+    let (name, mut flags) = match shape {
+        Shape::Rectangle => ("box", vec![]),
+        Shape::Rounded => ("box", vec!["rounded"]),
+        Shape::Diamond => ("diamond", vec![]),
+        Shape::Hexagon => ("hexagon", vec![]),
+        Shape::Ellipse => ("ellipse", vec![]),
+    };
+    if style.fill.is_some() {
+        flags.push("filled");
+    }
+    if style.dashed {
+        flags.push("dashed");
+    }
+    (name, flags)
+}
+
+fn style_attribute(flags: &[&str]) -> String {
+    match flags {
+        [] => String::new(),
+        [flag] => format!(", style={flag}"),
+        _ => format!(", style=\"{}\"", flags.join(",")),
+    }
+}
+
+/// Colors and width of a node or an edge
+fn theme_attributes(style: &Style) -> String {
+    let mut res = String::new();
+    if let Some(fill) = &style.fill {
+        res.push_str(format!(", fillcolor={}", color(fill)).as_str());
+    }
+    if let Some(stroke) = &style.stroke {
+        res.push_str(format!(", color={}", color(stroke)).as_str());
+    }
+    if let Some(text) = &style.text {
+        res.push_str(format!(", fontcolor={}", color(text)).as_str());
+    }
+    if let Some(width) = style.width {
+        res.push_str(format!(", penwidth={width}").as_str());
+    }
+    res
+}
+
+/// Dash and gray out code which can never run, keeping the other style flags
+fn unreachable_attributes(node: &GraphNode, flags: &[&'static str]) -> String {
+    if !node.unreachable {
+        return String::new();
+    }
+    let mut flags = flags.to_vec();
+    if !flags.contains(&"dashed") {
+        flags.push("dashed");
+    }
+    format!(
+        "{}, color=gray50, fontcolor=gray50",
+        style_attribute(&flags)
+    )
+}
 
 pub struct Dot {
     curly: bool,
+    /// file the nodes link back to
+    link: Option<String>,
+    options: RenderOptions,
 }
 
 impl Dot {
     pub fn new(curly: bool) -> Self {
-        Dot { curly }
+        Dot {
+            curly,
+            link: None,
+            options: RenderOptions::default(),
+        }
     }
-}
 
-impl GraphDisplay for Dot {
-    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Link every node to its lines in `file_name`, with the position as tooltip
+    pub fn with_link(mut self, file_name: &str) -> Self {
+        self.link = Some(file_name.to_owned());
+        self
+    }
+
+    fn link_attributes(&self, node: &GraphNode) -> String {
+        match (&self.link, &node.span) {
+            (Some(file_name), Some(span)) => {
+                let file_name = file_name.replace('\"', "\\\"");
+                format!(
+                    ", URL=\"{}#{}\", tooltip=\"{}:{}:{}\"",
+                    file_name,
+                    span.anchor(),
+                    file_name,
+                    span.start.line,
+                    span.start.column
+                )
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn header(&self) -> String {
         let mut res = "digraph {\n".to_string();
         if !self.curly {
             res.push_str("graph [splines=polyline];\n");
         }
+        let theme = &self.options.theme;
+        let mut font = String::new();
+        if let Some(family) = &theme.font.family {
+            font.push_str(format!(", fontname=\"{}\"", family.replace('\"', "\\\"")).as_str());
+        }
+        if let Some(size) = theme.font.size {
+            font.push_str(format!(", fontsize={size}").as_str());
+        }
+        if let Some(text) = &theme.font.color {
+            font.push_str(format!(", fontcolor={}", color(text)).as_str());
+        }
+        let mut graph = font.clone();
+        if let Some(background) = &theme.background {
+            graph.push_str(format!(", bgcolor={}", color(background)).as_str());
+        }
+        for (name, attributes) in [("graph", graph), ("node", font.clone()), ("edge", font)] {
+            if let Some(attributes) = attributes.strip_prefix(", ") {
+                res.push_str(format!("{name} [{attributes}];\n").as_str());
+            }
+        }
+        res
+    }
+
+    /// write nodes and edges of `graph`, every node id is prefixed with `prefix`
+    fn write_graph(&self, graph: &Graph, prefix: &str, res: &mut String) -> Result<()> {
         for (id, i) in graph.node_references() {
-            match i {
-                GraphNodeType::Begin => res.push_str(
-                    format!(
-                        "D{} [shape=box, style=rounded, label=\"begin\"];\n",
-                        id.index()
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::End => res.push_str(
-                    format!(
-                        "{{rank = sink; D{} [shape=box, style=rounded, label=\"end\"];}}\n",
-                        id.index()
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::Node(str) => res.push_str(
-                    format!(
-                        "D{} [shape=box, label=\"{}\"];\n",
-                        id.index(),
-                        str.replace('\"', "\\\"")
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::Choice(str) => res.push_str(
-                    format!(
-                        "D{} [shape=diamond, label=\"{}?\"];\n",
-                        id.index(),
-                        str.replace('\"', "\\\"")
-                    )
-                    .as_str(),
+            let label = match &i.node_type {
+                GraphNodeType::Begin => self.options.begin.replace('\"', "\\\""),
+                GraphNodeType::End => self.options.end.replace('\"', "\\\""),
+                GraphNodeType::Node(str)
+                | GraphNodeType::Suspend(str)
+                | GraphNodeType::Return(str)
+                | GraphNodeType::Jump(str) => str.replace('\"', "\\\""),
+                // every line is left justified, like code
+                GraphNodeType::Block(v) => format!(
+                    "{}\\l",
+                    v.join("\n").replace('\"', "\\\"").replace('\n', "\\l")
                 ),
+                GraphNodeType::Choice(str) | GraphNodeType::Loop(str) => {
+                    self.options.choice(str).replace('\"', "\\\"")
+                }
                 GraphNodeType::Dummy => {
                     return Err(Error::UnexpectedDummyGraphNode {
                         graph: graph.clone(),
                     });
-                } // GraphNodeType::Dummy => {} // all dummy node will be eliminated
+                } // all dummy node will be eliminated
+            };
+            let style = self.options.theme.node(&i.node_type);
+            let (shape, flags) = shape(&i.node_type, &style);
+            let node = format!(
+                "{}D{} [shape={}{}, label=\"{}\"{}{}{}]",
+                prefix,
+                id.index(),
+                shape,
+                style_attribute(&flags),
+                label,
+                theme_attributes(&style),
+                self.link_attributes(i),
+                unreachable_attributes(i, &flags)
+            );
+            if matches!(i.node_type, GraphNodeType::End) {
+                res.push_str(format!("{{rank = sink; {node};}}\n").as_str());
+            } else {
+                res.push_str(format!("{node};\n").as_str());
             }
         }
 
         for i in graph.edge_references() {
-            match i.weight() {
-                crate::graph::EdgeType::Normal => res.push_str(
-                    format!("D{} -> D{};\n", i.source().index(), i.target().index()).as_str(),
+            let xlabel = |x: &str| format!("xlabel=\"{}\"", x.replace('\"', "\\\""));
+            let (ports, mut attributes) = match i.weight() {
+                EdgeType::Normal => (("", ""), vec![]),
+                EdgeType::Branch(t) => (
+                    (if *t { ":s" } else { ":e" }, ":n"),
+                    vec![xlabel(self.options.branch(*t))],
                 ),
-                crate::graph::EdgeType::Branch(t) => res.push_str(
-                    format!(
-                        "D{}:{} -> D{}:n [xlabel={}];\n",
-                        i.source().index(),
-                        if *t { "s" } else { "e" },
-                        i.target().index(),
-                        if *t { "Y" } else { "N" }
-                    )
-                    .as_str(),
+                EdgeType::Exception(param) => {
+                    (("", ""), vec!["style=dashed".to_owned(), xlabel(param)])
+                }
+                EdgeType::Lambda(name) => (("", ""), vec!["style=dotted".to_owned(), xlabel(name)]),
+                EdgeType::Case(values) => (("", ":n"), vec![xlabel(values)]),
+                EdgeType::Resume => (
+                    ("", ""),
+                    vec!["style=dashed".to_owned(), "xlabel=resume".to_owned()],
                 ),
             };
+            let style = self.options.theme.edge(i.weight());
+            if style.dashed && !attributes.iter().any(|x| x.starts_with("style=")) {
+                attributes.push("style=dashed".to_owned());
+            }
+            let attributes = attributes.join(", ") + &theme_attributes(&style);
+            let attributes = attributes.trim_start_matches(", ");
+            res.push_str(
+                format!(
+                    "{}D{}{} -> {}D{}{}{};\n",
+                    prefix,
+                    i.source().index(),
+                    ports.0,
+                    prefix,
+                    i.target().index(),
+                    ports.1,
+                    if attributes.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" [{attributes}]")
+                    }
+                )
+                .as_str(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl GraphDisplay for Dot {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let mut res = self.header();
+        self.write_graph(graph, "", &mut res)?;
+        res.push_str("}\n");
+        Ok(res)
+    }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        let mut res = self.header();
+        for (idx, (name, graph)) in graphs.iter().enumerate() {
+            res.push_str(
+                format!(
+                    "subgraph cluster_{} {{\nlabel=\"{}\";\n",
+                    idx,
+                    name.replace('\"', "\\\"")
+                )
+                .as_str(),
+            );
+            self.write_graph(graph, &format!("F{idx}"), &mut res)?;
+            res.push_str("}\n");
         }
         res.push_str("}\n");
         Ok(res)
     }
+
+    fn file_extension(&self) -> &'static str {
+        "dot"
+    }
 }
@@ -0,0 +1,172 @@
+use crate::error::{Error, Result};
+use crate::graph::{EdgeType, Graph, GraphNodeType};
+use petgraph::{
+    visit::IntoNodeReferences,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use serde::Serialize;
+use std::borrow::Cow;
+
+use super::GraphDisplay;
+
+/// Bumped whenever the layout of the JSON output changes in an incompatible way
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Default)]
+pub struct Json {}
+
+impl Json {
+    pub fn new() -> Self {
+        Json {}
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRange {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct JsonLocation {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Serialize)]
+struct JsonSpan {
+    start: JsonLocation,
+    end: JsonLocation,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    id: usize,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<Cow<'a, str>>,
+    range: Option<JsonRange>,
+    span: Option<JsonSpan>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unreachable: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    source: usize,
+    target: usize,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    version: u32,
+    #[serde(flatten)]
+    graph: JsonGraph<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonCombinedDocument<'a> {
+    version: u32,
+    functions: Vec<JsonGraph<'a>>,
+}
+
+fn to_json_graph<'a>(name: Option<&'a str>, graph: &'a Graph) -> Result<JsonGraph<'a>> {
+    let mut nodes = Vec::new();
+    for (id, i) in graph.node_references() {
+        let (kind, text) = match &i.node_type {
+            GraphNodeType::Begin => ("begin", None),
+            GraphNodeType::End => ("end", None),
+            GraphNodeType::Node(str) => ("node", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Suspend(str) => ("suspend", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Block(v) => ("block", Some(Cow::Owned(v.join("\n")))),
+            GraphNodeType::Choice(str) => ("choice", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Loop(str) => ("loop", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Return(str) => ("return", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Jump(str) => ("jump", Some(Cow::Borrowed(str.as_str()))),
+            GraphNodeType::Dummy => {
+                return Err(Error::UnexpectedDummyGraphNode {
+                    graph: graph.clone(),
+                });
+            }
+        };
+        nodes.push(JsonNode {
+            id: id.index(),
+            kind,
+            text,
+            range: i.range.as_ref().map(|range| JsonRange {
+                start: range.start,
+                end: range.end,
+            }),
+            span: i.span.as_ref().map(|span| JsonSpan {
+                start: JsonLocation {
+                    line: span.start.line,
+                    column: span.start.column,
+                },
+                end: JsonLocation {
+                    line: span.end.line,
+                    column: span.end.column,
+                },
+            }),
+            unreachable: i.unreachable,
+        });
+    }
+    let edges = graph
+        .edge_references()
+        .map(|i| {
+            let (kind, branch, label) = match i.weight() {
+                EdgeType::Normal => ("normal", None, None),
+                EdgeType::Branch(t) => ("branch", Some(*t), None),
+                EdgeType::Exception(param) => ("exception", None, Some(param.as_str())),
+                EdgeType::Lambda(name) => ("lambda", None, Some(name.as_str())),
+                EdgeType::Resume => ("resume", None, None),
+                EdgeType::Case(values) => ("case", None, Some(values.as_str())),
+            };
+            JsonEdge {
+                source: i.source().index(),
+                target: i.target().index(),
+                kind,
+                branch,
+                label,
+            }
+        })
+        .collect();
+    Ok(JsonGraph { name, nodes, edges })
+}
+
+impl GraphDisplay for Json {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let document = JsonDocument {
+            version: SCHEMA_VERSION,
+            graph: to_json_graph(None, graph)?,
+        };
+        Ok(serde_json::to_string_pretty(&document)? + "\n")
+    }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        let document = JsonCombinedDocument {
+            version: SCHEMA_VERSION,
+            functions: graphs
+                .iter()
+                .map(|(name, graph)| to_json_graph(Some(name), graph))
+                .collect::<Result<_>>()?,
+        };
+        Ok(serde_json::to_string_pretty(&document)? + "\n")
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "json"
+    }
+}
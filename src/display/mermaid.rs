@@ -0,0 +1,245 @@
+use crate::error::{Error, Result};
+use crate::graph::{EdgeType, Graph, GraphNodeType};
+use itertools::Itertools;
+use petgraph::{
+    visit::IntoNodeReferences,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+
+use super::theme::{Shape, Style};
+use super::{GraphDisplay, RenderOptions};
+
+#[derive(Debug, Default)]
+pub struct Mermaid {
+    options: RenderOptions,
+}
+
+impl Mermaid {
+    pub fn new() -> Self {
+        Mermaid {
+            options: RenderOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Escape text so that it can be put inside a quoted mermaid label.
+/// `#` must go first, since every other replacement introduces one.
+fn escape(str: &str) -> String {
+    str.replace('#', "#35;")
+        .replace('\"', "#quot;")
+        .replace('[', "#91;")
+        .replace(']', "#93;")
+        .replace('{', "#123;")
+        .replace('}', "#125;")
+        .replace('(', "#40;")
+        .replace(')', "#41;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace('\n', "<br/>")
+}
+
+/// `fill:#ffffff,stroke:#000000` and the like, for the colors, width and dashes of `style`
+fn css(style: &Style) -> String {
+    let mut res = Vec::new();
+    if let Some(fill) = &style.fill {
+        res.push(format!("fill:{}", fill.hex()));
+    }
+    if let Some(stroke) = &style.stroke {
+        res.push(format!("stroke:{}", stroke.hex()));
+    }
+    if let Some(text) = &style.text {
+        res.push(format!("color:{}", text.hex()));
+    }
+    if let Some(width) = style.width {
+        res.push(format!("stroke-width:{width}px"));
+    }
+    if style.dashed {
+        res.push("stroke-dasharray:5 3".to_owned());
+    }
+    res.join(",")
+}
+
+/// Style of the class given to code which can never run
+const UNREACHABLE_CLASS: &str = "classDef unreachable stroke-dasharray: 5 3, color: gray\n";
+
+/// Styles of the nodes and edges written, set once all of them are
+#[derive(Default)]
+struct Styles {
+    /// Class of every styled kind of node, with its style
+    classes: Vec<(&'static str, String)>,
+    /// Style of every edge, in the order they are written
+    links: Vec<String>,
+    unreachable: bool,
+}
+
+impl Styles {
+    fn write(&self, res: &mut String) {
+        for (class, css) in &self.classes {
+            res.push_str(format!("classDef {class} {css}\n").as_str());
+        }
+        // edges are styled by their position, those with the same style in one go
+        let mut links: Vec<(&str, Vec<usize>)> = Vec::new();
+        for (idx, css) in self.links.iter().enumerate() {
+            if css.is_empty() {
+                continue;
+            }
+            match links.iter_mut().find(|(x, _)| x == css) {
+                Some((_, v)) => v.push(idx),
+                None => links.push((css, vec![idx])),
+            }
+        }
+        for (css, v) in links {
+            res.push_str(format!("linkStyle {} {}\n", v.iter().join(","), css).as_str());
+        }
+        if self.unreachable {
+            res.push_str(UNREACHABLE_CLASS);
+        }
+    }
+}
+
+impl Mermaid {
+    /// Font and background of the whole chart
+    fn header(&self) -> String {
+        let theme = &self.options.theme;
+        let mut variables = Vec::new();
+        if let Some(family) = &theme.font.family {
+            variables.push(format!("\"fontFamily\": {family:?}"));
+        }
+        if let Some(size) = theme.font.size {
+            variables.push(format!("\"fontSize\": \"{size}px\""));
+        }
+        if let Some(text) = &theme.font.color {
+            variables.push(format!("\"primaryTextColor\": \"{}\"", text.hex()));
+        }
+        if let Some(background) = &theme.background {
+            variables.push(format!("\"background\": \"{}\"", background.hex()));
+        }
+        let mut res = String::new();
+        if !variables.is_empty() {
+            res.push_str(
+                format!(
+                    "%%{{init: {{\"themeVariables\": {{{}}}}}}}%%\n",
+                    variables.join(", ")
+                )
+                .as_str(),
+            );
+        }
+        res.push_str("flowchart TD\n");
+        res
+    }
+
+    /// write nodes and edges of `graph`, every node id is prefixed with `prefix`
+    fn write_graph(
+        &self,
+        graph: &Graph,
+        prefix: &str,
+        res: &mut String,
+        styles: &mut Styles,
+    ) -> Result<()> {
+        for (id, i) in graph.node_references() {
+            let (label, shape, class) = match &i.node_type {
+                GraphNodeType::Begin => (self.options.begin.clone(), Shape::Rounded, "node_begin"),
+                GraphNodeType::End => (self.options.end.clone(), Shape::Rounded, "node_end"),
+                GraphNodeType::Node(str) => (str.clone(), Shape::Rectangle, "node_node"),
+                GraphNodeType::Suspend(str) => (str.clone(), Shape::Hexagon, "node_suspend"),
+                GraphNodeType::Block(v) => (v.join("\n"), Shape::Rectangle, "node_block"),
+                GraphNodeType::Choice(str) => {
+                    (self.options.choice(str), Shape::Diamond, "node_choice")
+                }
+                GraphNodeType::Loop(str) => (self.options.choice(str), Shape::Diamond, "node_loop"),
+                GraphNodeType::Return(str) => (str.clone(), Shape::Rounded, "node_return"),
+                GraphNodeType::Jump(str) => (str.clone(), Shape::Ellipse, "node_jump"),
+                GraphNodeType::Dummy => {
+                    return Err(Error::UnexpectedDummyGraphNode {
+                        graph: graph.clone(),
+                    });
+                }
+            };
+            let style = self.options.theme.node(&i.node_type);
+            let (open, close) = match style.shape.unwrap_or(shape) {
+                Shape::Rectangle => ("[", "]"),
+                Shape::Rounded => ("([", "])"),
+                Shape::Diamond => ("{", "}"),
+                Shape::Hexagon => ("{{", "}}"),
+                Shape::Ellipse => ("((", "))"),
+            };
+            res.push_str(
+                format!(
+                    "{}D{}{}\"{}\"{}\n",
+                    prefix,
+                    id.index(),
+                    open,
+                    escape(&label),
+                    close
+                )
+                .as_str(),
+            );
+            let css = css(&style);
+            if !css.is_empty() {
+                res.push_str(format!("class {}D{} {}\n", prefix, id.index(), class).as_str());
+                if !styles.classes.iter().any(|(x, _)| *x == class) {
+                    styles.classes.push((class, css));
+                }
+            }
+            if i.unreachable {
+                res.push_str(format!("class {}D{} unreachable\n", prefix, id.index()).as_str());
+                styles.unreachable = true;
+            }
+        }
+        for i in graph.edge_references() {
+            let arrow = match i.weight() {
+                EdgeType::Normal => "-->".to_owned(),
+                EdgeType::Branch(t) => format!("-- \"{}\" -->", escape(self.options.branch(*t))),
+                EdgeType::Exception(param) | EdgeType::Lambda(param) => {
+                    format!("-. \"{}\" .->", escape(param))
+                }
+                EdgeType::Case(values) => format!("-- \"{}\" -->", escape(values)),
+                EdgeType::Resume => "-. resume .->".to_owned(),
+            };
+            res.push_str(
+                format!(
+                    "{}D{} {} {}D{}\n",
+                    prefix,
+                    i.source().index(),
+                    arrow,
+                    prefix,
+                    i.target().index()
+                )
+                .as_str(),
+            );
+            styles.links.push(css(&self.options.theme.edge(i.weight())));
+        }
+        Ok(())
+    }
+}
+
+impl GraphDisplay for Mermaid {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let mut res = self.header();
+        let mut styles = Styles::default();
+        self.write_graph(graph, "", &mut res, &mut styles)?;
+        styles.write(&mut res);
+        Ok(res)
+    }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        let mut res = self.header();
+        let mut styles = Styles::default();
+        for (idx, (name, graph)) in graphs.iter().enumerate() {
+            res.push_str(format!("subgraph F{}[\"{}\"]\n", idx, escape(name)).as_str());
+            self.write_graph(graph, &format!("F{idx}"), &mut res, &mut styles)?;
+            res.push_str("end\n");
+        }
+        styles.write(&mut res);
+        Ok(res)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "mmd"
+    }
+}
@@ -1,21 +1,105 @@
 use enum_dispatch::enum_dispatch;
+use serde::Deserialize;
 
 use crate::{error::Result, graph::Graph};
 
 use self::d2::D2;
 use self::dot::Dot;
+use self::json::Json;
+use self::mermaid::Mermaid;
+use self::svg::Svg;
+use self::theme::Theme;
 use self::tikz::Tikz;
 
 pub mod d2;
 pub mod dot;
+pub mod json;
+pub mod mermaid;
+pub mod svg;
+pub mod theme;
 pub mod tikz;
+
+/// Language of the words a chart is drawn with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(target_family = "wasm"), derive(clap::ValueEnum))]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+    Ja,
+    De,
+    Fr,
+}
+
+/// How every backend draws the chart around the code, shared so that charts look the same in all
+/// of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// Label of the branch taken when a condition holds
+    pub yes: String,
+    /// Label of the branch taken when a condition fails
+    pub no: String,
+    pub begin: String,
+    pub end: String,
+    /// Whether conditions end with `?`, like `x > 0?`
+    pub question_mark: bool,
+    pub theme: Theme,
+}
+
+impl RenderOptions {
+    pub fn new(locale: Locale) -> Self {
+        let (yes, no, begin, end) = match locale {
+            Locale::En => ("Y", "N", "begin", "end"),
+            Locale::Zh => ("是", "否", "开始", "结束"),
+            Locale::Ja => ("はい", "いいえ", "開始", "終了"),
+            Locale::De => ("J", "N", "Anfang", "Ende"),
+            Locale::Fr => ("O", "N", "début", "fin"),
+        };
+        RenderOptions {
+            yes: yes.to_owned(),
+            no: no.to_owned(),
+            begin: begin.to_owned(),
+            end: end.to_owned(),
+            question_mark: true,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Label of a branch, see [`crate::graph::EdgeType::Branch`]
+    pub fn branch(&self, taken: bool) -> &str {
+        if taken { &self.yes } else { &self.no }
+    }
+
+    /// Text of a decision on `cond`
+    pub fn choice(&self, cond: &str) -> String {
+        if self.question_mark {
+            format!("{cond}?")
+        } else {
+            cond.to_owned()
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions::new(Locale::default())
+    }
+}
+
 #[enum_dispatch]
 pub enum GraphDisplayBackend {
     Dot,
     Tikz,
     D2,
+    Mermaid,
+    Json,
+    Svg,
 }
 #[enum_dispatch(GraphDisplayBackend)]
 pub trait GraphDisplay {
     fn generate_from_graph(&self, graph: &Graph) -> Result<String>;
+    /// Render several named graphs into a single document
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String>;
+    fn file_extension(&self) -> &'static str;
 }
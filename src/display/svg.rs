@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+use crate::graph::{EdgeType, Graph, GraphNode, GraphNodeType};
+use crate::layout::{self, Layout, Point, Rect, Size};
+use petgraph::{
+    visit::IntoNodeReferences,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+
+use super::theme::{Shape, Style};
+use super::{GraphDisplay, RenderOptions};
+
+pub(crate) const FONT_SIZE: f64 = 14.0;
+/// advance of a monospace glyph, relative to the font size
+pub(crate) const CHAR_WIDTH: f64 = 0.6 * FONT_SIZE;
+pub(crate) const LINE_HEIGHT: f64 = 18.0;
+pub(crate) const PADDING_X: f64 = 10.0;
+pub(crate) const PADDING_Y: f64 = 6.0;
+pub(crate) const TITLE_HEIGHT: f64 = 30.0;
+
+#[derive(Debug, Default)]
+pub struct Svg {
+    options: RenderOptions,
+}
+
+impl Svg {
+    pub fn new() -> Self {
+        Svg {
+            options: RenderOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+pub(crate) fn escape(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\"', "&quot;")
+}
+
+fn label(node_type: &GraphNodeType, options: &RenderOptions) -> String {
+    match node_type {
+        GraphNodeType::Begin => options.begin.clone(),
+        GraphNodeType::End => options.end.clone(),
+        GraphNodeType::Node(str)
+        | GraphNodeType::Suspend(str)
+        | GraphNodeType::Return(str)
+        | GraphNodeType::Jump(str) => str.clone(),
+        GraphNodeType::Block(v) => v.join("\n"),
+        GraphNodeType::Choice(str) | GraphNodeType::Loop(str) => options.choice(str),
+        GraphNodeType::Dummy => String::new(),
+    }
+}
+
+/// Shape of a node, the one of its kind unless the theme sets another
+fn shape(node_type: &GraphNodeType, options: &RenderOptions) -> Shape {
+    options
+        .theme
+        .node(node_type)
+        .shape
+        .unwrap_or(match node_type {
+            GraphNodeType::Begin | GraphNodeType::End | GraphNodeType::Return(_) => Shape::Rounded,
+            GraphNodeType::Choice(_) | GraphNodeType::Loop(_) => Shape::Diamond,
+            GraphNodeType::Suspend(_) => Shape::Hexagon,
+            GraphNodeType::Jump(_) => Shape::Ellipse,
+            _ => Shape::Rectangle,
+        })
+}
+
+/// Size of a node, with its text `scale` times the size of [`FONT_SIZE`]
+fn measure(node_type: &GraphNodeType, options: &RenderOptions, scale: f64) -> Size {
+    let text = label(node_type, options);
+    let lines = text.lines().count().max(1) as f64;
+    let chars = text.lines().map(|x| x.chars().count()).max().unwrap_or(0) as f64;
+    let width = chars * CHAR_WIDTH * scale + 2.0 * PADDING_X;
+    let height = lines * LINE_HEIGHT * scale + 2.0 * PADDING_Y;
+    match shape(node_type, options) {
+        // the text box has to fit inside the diamond
+        Shape::Diamond => Size {
+            width: width * 1.5,
+            height: height * 2.0,
+        },
+        // and between the slanted sides of the hexagon
+        Shape::Hexagon => Size {
+            width: width + height,
+            height,
+        },
+        // and inside the ellipse
+        Shape::Ellipse => Size {
+            width: width * 1.5,
+            height: height * 1.5,
+        },
+        Shape::Rectangle | Shape::Rounded => Size { width, height },
+    }
+}
+
+fn polyline(points: &[Point]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{:.1},{:.1}", p.x, p.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Inline style of the theme, it wins over the classes.
+/// `text` asks for the style of the text of a node or an edge.
+fn theme_style(style: &Style, text: bool) -> Vec<String> {
+    let mut res = Vec::new();
+    if text {
+        if let Some(text) = &style.text {
+            res.push(format!("fill: {}", text.hex()));
+        }
+        return res;
+    }
+    if let Some(fill) = &style.fill {
+        res.push(format!("fill: {}", fill.hex()));
+    }
+    if let Some(stroke) = &style.stroke {
+        res.push(format!("stroke: {}", stroke.hex()));
+    }
+    if let Some(width) = style.width {
+        res.push(format!("stroke-width: {width}"));
+    }
+    if style.dashed {
+        res.push("stroke-dasharray: 5 3".to_owned());
+    }
+    res
+}
+
+/// Dash and gray out code which can never run, over the style of the theme
+fn unreachable_style(node: &GraphNode, text: bool) -> &'static [&'static str] {
+    match (node.unreachable, text) {
+        (false, _) => &[],
+        (true, false) => &["stroke: gray", "stroke-dasharray: 5 3"],
+        (true, true) => &["fill: gray"],
+    }
+}
+
+fn style_attribute(styles: &[String]) -> String {
+    if styles.is_empty() {
+        String::new()
+    } else {
+        format!(" style=\"{}\"", styles.join("; "))
+    }
+}
+
+/// Write `text` centered in `rect`, or left aligned like code if `left` is set
+fn write_text(res: &mut String, text: &str, rect: &Rect, style: &str, left: bool, scale: f64) {
+    let line_height = LINE_HEIGHT * scale;
+    let lines: Vec<&str> = text.lines().collect();
+    let first = rect.y - (lines.len().max(1) as f64 - 1.0) * line_height / 2.0;
+    let (x, anchor) = if left {
+        (rect.x - rect.width / 2.0 + PADDING_X, "start")
+    } else {
+        (rect.x, "middle")
+    };
+    res.push_str(
+        format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" dominant-baseline=\"central\"{}>",
+            x, first, anchor, style
+        )
+        .as_str(),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        res.push_str(
+            format!(
+                "<tspan x=\"{:.1}\" y=\"{:.1}\" xml:space=\"preserve\">{}</tspan>",
+                x,
+                first + i as f64 * line_height,
+                escape(line)
+            )
+            .as_str(),
+        );
+    }
+    res.push_str("</text>\n");
+}
+
+impl Svg {
+    /// Size of the text of the theme, relative to [`FONT_SIZE`]
+    fn scale(&self) -> f64 {
+        self.options.theme.font.size.map_or(1.0, |x| x / FONT_SIZE)
+    }
+
+    fn layout(&self, graph: &Graph) -> Result<(Layout, Vec<GraphNode>, Vec<EdgeType>)> {
+        let mut index = HashMap::new();
+        let mut sizes = Vec::new();
+        let mut nodes = Vec::new();
+        let mut source = None;
+        let mut sink = None;
+        for (id, i) in graph.node_references() {
+            match i.node_type {
+                GraphNodeType::Dummy => {
+                    return Err(Error::UnexpectedDummyGraphNode {
+                        graph: graph.clone(),
+                    });
+                }
+                GraphNodeType::Begin => source = Some(sizes.len()),
+                GraphNodeType::End => sink = Some(sizes.len()),
+                _ => {}
+            }
+            index.insert(id, sizes.len());
+            sizes.push(measure(&i.node_type, &self.options, self.scale()));
+            nodes.push(i.clone());
+        }
+        let mut edges = Vec::new();
+        let mut weights = Vec::new();
+        for i in graph.edge_references() {
+            edges.push((index[&i.source()], index[&i.target()]));
+            weights.push(i.weight().clone());
+        }
+        Ok((layout::layout(&sizes, &edges, source, sink), nodes, weights))
+    }
+
+    /// write a laid out graph, shifted down by `offset`
+    fn write_graph(&self, graph: &Graph, offset: f64, res: &mut String) -> Result<(f64, f64)> {
+        let (layout, nodes, weights) = self.layout(graph)?;
+        res.push_str(format!("<g transform=\"translate(0,{offset:.1})\">\n").as_str());
+        for (rect, node) in layout.nodes.iter().zip(&nodes) {
+            let (left, top) = (rect.x - rect.width / 2.0, rect.y - rect.height / 2.0);
+            let theme = self.options.theme.node(&node.node_type);
+            let style = |text| {
+                let mut res = theme_style(&theme, text);
+                res.extend(unreachable_style(node, text).iter().map(|x| x.to_string()));
+                style_attribute(&res)
+            };
+            let class = match node.node_type {
+                GraphNodeType::Begin | GraphNodeType::End => "terminal",
+                GraphNodeType::Choice(_) => "choice",
+                GraphNodeType::Loop(_) => "loop",
+                GraphNodeType::Suspend(_) => "suspend",
+                GraphNodeType::Return(_) => "return",
+                GraphNodeType::Jump(_) => "jump",
+                _ => "node",
+            };
+            match shape(&node.node_type, &self.options) {
+                Shape::Rectangle | Shape::Rounded => res.push_str(
+                    format!(
+                        "<rect class=\"{}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"{}{}/>\n",
+                        class,
+                        left,
+                        top,
+                        rect.width,
+                        rect.height,
+                        match shape(&node.node_type, &self.options) {
+                            Shape::Rounded => format!(" rx=\"{:.1}\"", rect.height / 2.0),
+                            _ => String::new(),
+                        },
+                        style(false)
+                    )
+                    .as_str(),
+                ),
+                Shape::Diamond => res.push_str(
+                    format!(
+                        "<polygon class=\"{}\" points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"{}/>\n",
+                        class,
+                        rect.x,
+                        top,
+                        left + rect.width,
+                        rect.y,
+                        rect.x,
+                        top + rect.height,
+                        left,
+                        rect.y,
+                        style(false)
+                    )
+                    .as_str(),
+                ),
+                Shape::Hexagon => res.push_str(
+                    format!(
+                        "<polygon class=\"{}\" points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"{}/>\n",
+                        class,
+                        left,
+                        rect.y,
+                        left + rect.height / 2.0,
+                        top,
+                        left + rect.width - rect.height / 2.0,
+                        top,
+                        left + rect.width,
+                        rect.y,
+                        left + rect.width - rect.height / 2.0,
+                        top + rect.height,
+                        left + rect.height / 2.0,
+                        top + rect.height,
+                        style(false)
+                    )
+                    .as_str(),
+                ),
+                Shape::Ellipse => res.push_str(
+                    format!(
+                        "<ellipse class=\"{}\" cx=\"{:.1}\" cy=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\"{}/>\n",
+                        class,
+                        rect.x,
+                        rect.y,
+                        rect.width / 2.0,
+                        rect.height / 2.0,
+                        style(false)
+                    )
+                    .as_str(),
+                ),
+            }
+            write_text(
+                res,
+                &label(&node.node_type, &self.options),
+                rect,
+                &style(true),
+                matches!(node.node_type, GraphNodeType::Block(_)),
+                self.scale(),
+            );
+        }
+        for ((points, at), weight) in layout.edges.iter().zip(&layout.labels).zip(&weights) {
+            let (class, text) = match weight {
+                EdgeType::Normal => ("edge", None),
+                EdgeType::Branch(t) => ("edge", Some(self.options.branch(*t))),
+                EdgeType::Exception(param) => ("edge exception", Some(param.as_str())),
+                EdgeType::Lambda(name) => ("edge lambda", Some(name.as_str())),
+                EdgeType::Resume => ("edge resume", Some("resume")),
+                EdgeType::Case(values) => ("edge", Some(values.as_str())),
+            };
+            let theme = self.options.theme.edge(weight);
+            res.push_str(
+                format!(
+                    "<polyline class=\"{}\" points=\"{}\" marker-end=\"url(#arrow)\"{}/>\n",
+                    class,
+                    polyline(points),
+                    style_attribute(&theme_style(&theme, false))
+                )
+                .as_str(),
+            );
+            if let Some(text) = text {
+                res.push_str(
+                    format!(
+                        "<text class=\"label\" x=\"{:.1}\" y=\"{:.1}\"{}>{}</text>\n",
+                        at.x,
+                        at.y,
+                        style_attribute(&theme_style(&theme, true)),
+                        escape(text)
+                    )
+                    .as_str(),
+                );
+            }
+        }
+        res.push_str("</g>\n");
+        Ok((layout.width, layout.height))
+    }
+
+    fn document(&self, body: &str, width: f64, height: f64) -> String {
+        let theme = &self.options.theme;
+        let mut text = format!(
+            "font-family: {}; font-size: {}px;",
+            theme.font.family.as_deref().unwrap_or("monospace"),
+            FONT_SIZE * self.scale()
+        );
+        if let Some(color) = &theme.font.color {
+            text.push_str(format!(" fill: {};", color.hex()).as_str());
+        }
+        let label = 12.0 * self.scale();
+        let background = theme.background.as_ref().map_or("white", |x| x.hex());
+        // arrow heads take the color of plain edges
+        let arrow = theme
+            .edge(&EdgeType::Normal)
+            .stroke
+            .map(|x| format!(" fill=\"{}\"", x.hex()))
+            .unwrap_or_default();
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.0}" height="{height:.0}" viewBox="0 0 {width:.1} {height:.1}">
+<defs>
+<marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto-start-reverse">
+<path d="M 0 0 L 10 5 L 0 10 z"{arrow}/>
+</marker>
+<style>
+text {{ {text} }}
+.node, .terminal, .choice, .loop, .suspend, .return, .jump {{ fill: white; stroke: black; stroke-width: 1.5; }}
+.edge {{ fill: none; stroke: black; stroke-width: 1.2; }}
+.exception {{ stroke-dasharray: 5 3; }}
+.lambda {{ stroke-dasharray: 2 3; }}
+.resume {{ stroke-dasharray: 5 3; }}
+.label {{ font-size: {label}px; }}
+.title {{ font-weight: bold; }}
+</style>
+</defs>
+<rect width="100%" height="100%" fill="{background}"/>
+{body}</svg>
+"#
+        )
+    }
+}
+
+impl GraphDisplay for Svg {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let mut body = String::new();
+        let (width, height) = self.write_graph(graph, 0.0, &mut body)?;
+        Ok(self.document(&body, width, height))
+    }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        // charts are stacked vertically, each below its title
+        let mut body = String::new();
+        let (mut width, mut height) = (0.0, 0.0);
+        for (name, graph) in graphs {
+            body.push_str(
+                format!(
+                    "<text class=\"title\" x=\"20\" y=\"{:.1}\">{}</text>\n",
+                    height + TITLE_HEIGHT - 8.0,
+                    escape(name)
+                )
+                .as_str(),
+            );
+            let (w, h) = self.write_graph(graph, height + TITLE_HEIGHT, &mut body)?;
+            width = f64::max(width, w);
+            height += TITLE_HEIGHT + h;
+        }
+        Ok(self.document(&body, width, height))
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "svg"
+    }
+}
@@ -0,0 +1,212 @@
+//! Themes, setting how every kind of node and edge looks.
+//!
+//! A theme is read from TOML. Nothing is styled unless the theme says so, so that every backend
+//! keeps its own look for the rest. Structograms have no kinds of nodes and edges, they take
+//! the background, the font family and color, and the `all` style of nodes:
+//!
+//! ```toml
+//! background = "#1e1e1e"
+//!
+//! [font]
+//! family = "Fira Code"
+//! size = 12
+//! color = "#d4d4d4"
+//!
+//! [nodes.all]
+//! stroke = "#d4d4d4"
+//!
+//! [nodes.choice]
+//! shape = "hexagon"
+//! fill = "#3a3d41"
+//!
+//! [edges.exception]
+//! stroke = "#f48771"
+//! dashed = true
+//! ```
+
+use miette::{NamedSource, SourceSpan};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::graph::{EdgeType, GraphNodeType};
+
+/// Themes built into cxx2flow, as (name, TOML)
+pub const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("monochrome", include_str!("themes/monochrome.toml")),
+    ("dark", include_str!("themes/dark.toml")),
+];
+
+/// A color like `#1e1e1e`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(String);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let digits = value.strip_prefix('#').unwrap_or_default();
+        if digits.len() == 6 && digits.chars().all(|x| x.is_ascii_hexdigit()) {
+            Ok(Color(value.to_ascii_lowercase()))
+        } else {
+            Err(format!("expected a color like \"#1e1e1e\", got {value:?}"))
+        }
+    }
+}
+
+impl Color {
+    /// `#1e1e1e`
+    pub fn hex(&self) -> &str {
+        &self.0
+    }
+
+    /// Red, green and blue, from 0 to 255
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        let channel = |x| u8::from_str_radix(&self.0[x..x + 2], 16).unwrap_or_default();
+        (channel(1), channel(3), channel(5))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    Rectangle,
+    /// A rectangle with round ends
+    Rounded,
+    Diamond,
+    Hexagon,
+    Ellipse,
+}
+
+/// Look of a node or an edge, every field left out keeps the look of the backend
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    /// Only nodes have a shape
+    pub shape: Option<Shape>,
+    /// Only nodes are filled
+    pub fill: Option<Color>,
+    /// Color of the outline of a node, or of an edge
+    pub stroke: Option<Color>,
+    /// Color of the text
+    pub text: Option<Color>,
+    /// Width of the outline of a node, or of an edge, in points
+    pub width: Option<f64>,
+    pub dashed: bool,
+}
+
+impl Style {
+    /// Fields of `self`, falling back to those of `other`
+    fn or(&self, other: &Style) -> Style {
+        Style {
+            shape: self.shape.or(other.shape),
+            fill: self.fill.clone().or_else(|| other.fill.clone()),
+            stroke: self.stroke.clone().or_else(|| other.stroke.clone()),
+            text: self.text.clone().or_else(|| other.text.clone()),
+            width: self.width.or(other.width),
+            dashed: self.dashed || other.dashed,
+        }
+    }
+}
+
+/// Style of every kind of node, named like the node kinds of the JSON output.
+/// `loop` falls back to `choice`, `return` and `jump` to `node`, then `all` applies to the
+/// kinds which leave a field out.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeStyles {
+    pub all: Style,
+    pub begin: Style,
+    pub end: Style,
+    pub node: Style,
+    pub block: Style,
+    pub suspend: Style,
+    pub choice: Style,
+    pub r#loop: Style,
+    pub r#return: Style,
+    pub jump: Style,
+}
+
+/// Style of every kind of edge, named like the edge kinds of the JSON output.
+/// `all` applies to the kinds which leave a field out.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeStyles {
+    pub all: Style,
+    pub normal: Style,
+    pub branch: Style,
+    pub exception: Style,
+    pub lambda: Style,
+    pub resume: Style,
+    pub case: Style,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Font {
+    pub family: Option<String>,
+    /// In points
+    pub size: Option<f64>,
+    /// Color of text outside of nodes and edges, like titles, and of those not styled
+    pub color: Option<Color>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub background: Option<Color>,
+    pub font: Font,
+    pub nodes: NodeStyles,
+    pub edges: EdgeStyles,
+}
+
+impl Theme {
+    /// Read a theme from TOML, `file_name` is used in errors
+    pub fn from_toml(content: &str, file_name: &str) -> Result<Theme> {
+        toml::from_str(content).map_err(|error| Error::InvalidTheme {
+            src: NamedSource::new(file_name, content.to_owned()),
+            range: error.span().map(SourceSpan::from),
+            message: error.message().to_owned(),
+        })
+    }
+
+    /// One of [`BUILTIN_THEMES`]
+    pub fn builtin(name: &str) -> Option<Result<Theme>> {
+        BUILTIN_THEMES
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(name, content)| Theme::from_toml(content, name))
+    }
+
+    pub fn node(&self, node_type: &GraphNodeType) -> Style {
+        let style = match node_type {
+            GraphNodeType::Begin => self.nodes.begin.clone(),
+            GraphNodeType::End => self.nodes.end.clone(),
+            GraphNodeType::Node(_) | GraphNodeType::Dummy => self.nodes.node.clone(),
+            GraphNodeType::Block(_) => self.nodes.block.clone(),
+            GraphNodeType::Suspend(_) => self.nodes.suspend.clone(),
+            GraphNodeType::Choice(_) => self.nodes.choice.clone(),
+            GraphNodeType::Loop(_) => self.nodes.r#loop.or(&self.nodes.choice),
+            GraphNodeType::Return(_) => self.nodes.r#return.or(&self.nodes.node),
+            GraphNodeType::Jump(_) => self.nodes.jump.or(&self.nodes.node),
+        };
+        style.or(&self.nodes.all)
+    }
+
+    pub fn edge(&self, edge_type: &EdgeType) -> Style {
+        let style = match edge_type {
+            EdgeType::Normal => &self.edges.normal,
+            EdgeType::Branch(_) => &self.edges.branch,
+            EdgeType::Exception(_) => &self.edges.exception,
+            EdgeType::Lambda(_) => &self.edges.lambda,
+            EdgeType::Resume => &self.edges.resume,
+            EdgeType::Case(_) => &self.edges.case,
+        };
+        // edges are lines, with neither shape nor fill
+        Style {
+            shape: None,
+            fill: None,
+            ..style.or(&self.edges.all)
+        }
+    }
+}
@@ -1,21 +1,30 @@
+use itertools::Itertools;
 use petgraph::visit::{EdgeRef, IntoEdgeReferences, IntoNodeReferences};
 
 use crate::error::{Error, Result};
-use crate::graph::{Graph, GraphNodeType};
+use crate::graph::{EdgeType, Graph, GraphNode, GraphNodeType};
 
-use super::GraphDisplay;
+use super::theme::{Color, Shape, Style};
+use super::{GraphDisplay, RenderOptions};
 #[derive(Debug, Default)]
-pub struct Tikz {}
+pub struct Tikz {
+    options: RenderOptions,
+}
 
 impl Tikz {
     pub fn new() -> Self {
-        Tikz {}
+        Tikz {
+            options: RenderOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
     }
 }
 
-impl GraphDisplay for Tikz {
-    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
-        let mut res = r#"
+const PREAMBLE: &str = r#"
 \documentclass[tikz,border=10pt]{standalone}
 \usepackage{ctex}
 \usetikzlibrary{graphdrawing}
@@ -34,78 +43,193 @@ impl GraphDisplay for Tikz {
    text centered, minimum height=1em,
    execute at begin node={\begin{varwidth}{8em}},
    execute at end node={\end{varwidth}}]
-\begin{document}
-\tikz [layered layout, sibling distance=3cm] {
-  "#
-        .to_string();
+"#;
+
+fn color(color: &Color) -> String {
+    let (red, green, blue) = color.rgb();
+    format!("{{rgb,255:red,{red};green,{green};blue,{blue}}}")
+}
+
+/// `, fill=...` and the like, for the colors, width and dashes of `style`
+fn theme_options(style: &Style, dashed: bool) -> String {
+    let mut res = String::new();
+    if let Some(fill) = &style.fill {
+        res.push_str(format!(", fill={}", color(fill)).as_str());
+    }
+    if let Some(stroke) = &style.stroke {
+        res.push_str(format!(", draw={}", color(stroke)).as_str());
+    }
+    if let Some(text) = &style.text {
+        res.push_str(format!(", text={}", color(text)).as_str());
+    }
+    if let Some(width) = style.width {
+        res.push_str(format!(", line width={width}pt").as_str());
+    }
+    if style.dashed && !dashed {
+        res.push_str(", dashed");
+    }
+    res
+}
+
+/// Text set verbatim on a single line, as the labels may hold any character
+fn verbatim(text: &str) -> String {
+    format!("\\spverb${}$", text.replace('%', "\\%"))
+}
+
+/// Dash and gray out code which can never run
+fn unreachable_style(node: &GraphNode) -> &'static str {
+    if node.unreachable {
+        ", dashed, gray"
+    } else {
+        ""
+    }
+}
+
+impl Tikz {
+    /// Preamble of the document, with the font and background of the theme
+    fn preamble(&self) -> String {
+        let theme = &self.options.theme;
+        let mut res = PREAMBLE.to_string();
+        if let Some(family) = &theme.font.family {
+            res.push_str(
+                format!("\\setmainfont{{{family}}}\n\\setmonofont{{{family}}}\n").as_str(),
+            );
+        }
+        let mut block = Vec::new();
+        if let Some(size) = theme.font.size {
+            block.push(format!(
+                "font=\\fontsize{{{}}}{{{}}}\\selectfont",
+                size,
+                size * 1.2
+            ));
+        }
+        if let Some(text) = &theme.font.color {
+            block.push(format!("text={}", color(text)));
+        }
+        if !block.is_empty() {
+            res.push_str(
+                format!(
+                    "\\tikzset{{every node/.append style={{{}}}}}\n",
+                    block.join(", ")
+                )
+                .as_str(),
+            );
+        }
+        res.push_str("\\begin{document}\n");
+        if let Some(background) = &theme.background {
+            let (red, green, blue) = background.rgb();
+            res.push_str(format!("\\pagecolor[RGB]{{{red},{green},{blue}}}\n").as_str());
+        }
+        res
+    }
+
+    fn write_graph(&self, graph: &Graph, res: &mut String) -> Result<()> {
+        res.push_str("\\tikz [layered layout, sibling distance=3cm] {\n  ");
         for (id, i) in graph.node_references() {
-            match i {
-                GraphNodeType::Begin => res.push_str(
-                    format!(
-                        "\\node[draw] (D{}) [rounded rectangle, block] {{ Begin }};\n",
-                        id.index()
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::End => res.push_str(
-                    format!(
-                        "\\node[draw] (D{}) [rounded rectangle, block] {{ End }};\n",
-                        id.index()
-                    )
-                    .as_str(),
-                ),
-                GraphNodeType::Node(str) => res.push_str(
-                    format!(
-                        "\\node[draw] (D{}) [rectangle, block] {{ \\spverb${}$ }};\n",
-                        id.index(),
-                        str.replace('%', "\\%")
-                    )
-                    .replace('\n', " ")
-                    .as_str(),
-                ),
-                GraphNodeType::Choice(str) => res.push_str(
-                    format!(
-                        "\\node[draw] (D{}) [diamond, aspect=2, block] {{ \\spverb${}$ }};\n",
-                        id.index(),
-                        str.replace('%', "\\%")
-                    )
-                    .replace('\n', " ")
-                    .as_str(),
+            let (text, shape) = match &i.node_type {
+                GraphNodeType::Begin => (verbatim(&self.options.begin), Shape::Rounded),
+                GraphNodeType::End => (verbatim(&self.options.end), Shape::Rounded),
+                GraphNodeType::Node(str) => (verbatim(str), Shape::Rectangle),
+                GraphNodeType::Suspend(str) => (verbatim(str), Shape::Hexagon),
+                GraphNodeType::Return(str) => (verbatim(str), Shape::Rounded),
+                GraphNodeType::Jump(str) => (verbatim(str), Shape::Ellipse),
+                // one verbatim line per line of the block
+                GraphNodeType::Block(v) => (
+                    v.iter().flat_map(|x| x.lines()).map(verbatim).join("\\\\ "),
+                    Shape::Rectangle,
                 ),
+                GraphNodeType::Choice(str) | GraphNodeType::Loop(str) => {
+                    (verbatim(&self.options.choice(str)), Shape::Diamond)
+                }
                 GraphNodeType::Dummy => {
                     return Err(Error::UnexpectedDummyGraphNode {
                         graph: graph.clone(),
                     });
                 } // all dummy node will be eliminated
+            };
+            let style = self.options.theme.node(&i.node_type);
+            let node = format!(
+                "\\node[draw] (D{}) [{}, block{}{}] {{ {} }};\n",
+                id.index(),
+                match style.shape.unwrap_or(shape) {
+                    Shape::Rectangle => "rectangle",
+                    Shape::Rounded => "rounded rectangle",
+                    Shape::Diamond => "diamond, aspect=2",
+                    Shape::Hexagon => "chamfered rectangle",
+                    Shape::Ellipse => "ellipse",
+                },
+                theme_options(&style, false),
+                unreachable_style(i),
+                text
+            );
+            // verbatim text is kept on a single line
+            match &i.node_type {
+                GraphNodeType::Begin
+                | GraphNodeType::End
+                | GraphNodeType::Node(_)
+                | GraphNodeType::Suspend(_)
+                | GraphNodeType::Return(_)
+                | GraphNodeType::Jump(_)
+                | GraphNodeType::Choice(_)
+                | GraphNodeType::Loop(_) => res.push_str(node.replace('\n', " ").as_str()),
+                _ => res.push_str(node.as_str()),
             }
         }
         for i in graph.edge_references() {
-            match i.weight() {
-                crate::graph::EdgeType::Normal => res.push_str(
-                    format!(
-                        "\\draw (D{}) edge[->] (D{});\n",
+            let (line, label) = match i.weight() {
+                EdgeType::Normal => ("", None),
+                EdgeType::Branch(t) => ("", Some(verbatim(self.options.branch(*t)))),
+                EdgeType::Exception(param) => (", dashed", Some(verbatim(param))),
+                EdgeType::Lambda(name) => (", dotted", Some(verbatim(name))),
+                EdgeType::Case(values) => ("", Some(verbatim(values))),
+                EdgeType::Resume => (", dashed", Some("resume".to_owned())),
+            };
+            let theme = theme_options(&self.options.theme.edge(i.weight()), !line.is_empty());
+            res.push_str(
+                match label {
+                    Some(label) => format!(
+                        "\\draw (D{}) edge[->{}, below{}] node {{ {} }} (D{});\n",
                         i.source().index(),
+                        line,
+                        theme,
+                        label,
                         i.target().index()
-                    )
-                    .as_str(),
-                ),
-                crate::graph::EdgeType::Branch(t) => res.push_str(
-                    format!(
-                        "\\draw (D{}) edge[->, below] node {{ {} }} (D{});\n",
+                    ),
+                    None => format!(
+                        "\\draw (D{}) edge[->{}] (D{});\n",
                         i.source().index(),
-                        i.target().index(),
-                        if *t { "Y" } else { "N" }
-                    )
-                    .as_str(),
-                ),
-            }
+                        theme,
+                        i.target().index()
+                    ),
+                }
+                .as_str(),
+            );
         }
-        res.push_str(
-            r#"
+        res.push_str("\n}\n");
+        Ok(())
+    }
 }
-\end{document}
-  "#,
-        );
+
+impl GraphDisplay for Tikz {
+    fn generate_from_graph(&self, graph: &Graph) -> Result<String> {
+        let mut res = self.preamble();
+        self.write_graph(graph, &mut res)?;
+        res.push_str("\\end{document}\n  ");
         Ok(res)
     }
+
+    fn generate_from_graphs(&self, graphs: &[(String, Graph)]) -> Result<String> {
+        // every picture becomes a separate page of the standalone document
+        let mut res = self.preamble();
+        for (name, graph) in graphs {
+            res.push_str(format!("% {}\n", name).as_str());
+            self.write_graph(graph, &mut res)?;
+        }
+        res.push_str("\\end{document}\n  ");
+        Ok(res)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "tex"
+    }
 }
@@ -1,5 +1,7 @@
 #![allow(unused_assignments)]
 
+use std::sync::Arc;
+
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use petgraph::graph::NodeIndex;
 use thiserror::Error;
@@ -27,6 +29,31 @@ pub enum Error {
     #[diagnostic(code(cxx2flow::from_utf8), help("error with UTF-8 decoding"))]
     FromUTF8(#[from] std::string::FromUtf8Error),
 
+    #[error("transparent")]
+    #[diagnostic(code(cxx2flow::json), help("error with JSON serialization"))]
+    Json(#[from] serde_json::Error),
+
+    #[cfg(not(target_family = "wasm"))]
+    #[error("transparent")]
+    #[diagnostic(code(cxx2flow::lsp), help("error with the language server protocol"))]
+    Lsp(#[from] lsp_server::ProtocolError),
+
+    #[cfg(not(target_family = "wasm"))]
+    #[error("language server connection closed")]
+    #[diagnostic(
+        code(cxx2flow::lsp_disconnected),
+        help("the editor closed the connection before the server shut down")
+    )]
+    LspDisconnected,
+
+    #[cfg(not(target_family = "wasm"))]
+    #[error("preprocessor failed:\n{stderr}")]
+    #[diagnostic(
+        code(cxx2flow::preprocessor),
+        help("check the include directories and macros passed to the preprocessor")
+    )]
+    PreprocessorFailed { stderr: String },
+
     #[error("transparent")]
     #[diagnostic(
         code(cxx2flow::hashchain_insert_failed),
@@ -37,13 +64,31 @@ pub enum Error {
     #[error("target function not found")]
     #[diagnostic(
         code(cxx2flow::target_function_not_found),
-        help("maybe you have a typo, or source code is incomplete, \nplease check your input")
+        help(
+            "maybe you have a typo, or source code is incomplete, \nplease check your input{suggestion}"
+        )
     )]
     FunctionNotFound {
         #[source_code]
         src: String,
         #[label("this is the name of your target function")]
         range: SourceSpan,
+        suggestion: String,
+    },
+
+    #[error("target function is ambiguous")]
+    #[diagnostic(
+        code(cxx2flow::ambiguous_function),
+        help(
+            "more than one function matches this name, \nplease qualify it or add a parameter list, e.g. `run(int)`. \ncandidates are:{candidates}"
+        )
+    )]
+    AmbiguousFunction {
+        #[source_code]
+        src: String,
+        #[label("this name matches more than one function")]
+        range: SourceSpan,
+        candidates: String,
     },
 
     #[error("declarator not found")]
@@ -95,6 +140,20 @@ pub enum Error {
         range: SourceSpan,
     },
 
+    #[diagnostic(
+        code(cxx2flow::unstructured_goto),
+        help(
+            "structograms can only show structured control flow, \nplease draw a flowchart of functions using goto"
+        )
+    )]
+    #[error("goto cannot be drawn in a structogram")]
+    UnstructuredGoto {
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("goto statement here")]
+        range: SourceSpan,
+    },
+
     #[diagnostic(
         code(cxx2flow::unexpected_dummy_graph),
         help(
@@ -104,6 +163,71 @@ pub enum Error {
     #[error("unexpected dummy graph node {:?}", petgraph::dot::Dot::new(.graph))]
     UnexpectedDummyGraphNode { graph: Graph },
 
+    #[error("invalid theme: {message}")]
+    #[diagnostic(
+        code(cxx2flow::invalid_theme),
+        help(
+            "a theme sets background, [font], [nodes.<kind>] and [edges.<kind>], \
+             like the built-in themes monochrome and dark"
+        )
+    )]
+    InvalidTheme {
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{message}")]
+        range: Option<SourceSpan>,
+        message: String,
+    },
+
+    #[error("theme {name} not found")]
+    #[diagnostic(
+        code(cxx2flow::theme_not_found),
+        help("give the path of a TOML theme, or one of the built-in themes: {builtins}")
+    )]
+    ThemeNotFound { name: String, builtins: String },
+
+    #[error("invalid configuration: {message}")]
+    #[diagnostic(
+        code(cxx2flow::invalid_config),
+        help("options are named like on the command line, without the leading dashes")
+    )]
+    InvalidConfig {
+        // behind an Arc, as a second theme-sized variant would make every error larger
+        #[source_code]
+        src: Arc<NamedSource<String>>,
+        #[label("{message}")]
+        range: Option<SourceSpan>,
+        message: String,
+    },
+
+    #[error("profile {name} not found")]
+    #[diagnostic(
+        code(cxx2flow::profile_not_found),
+        help("the profiles of {file} are: {profiles}")
+    )]
+    ProfileNotFound {
+        name: String,
+        file: String,
+        profiles: String,
+    },
+
+    #[error("profile {name} not found, as there is no configuration")]
+    #[diagnostic(
+        code(cxx2flow::config_not_found),
+        help("profiles are defined in a .cxx2flow.toml in the directory of the input or above it")
+    )]
+    ConfigNotFound { name: String },
+
+    #[error("{option} requires {required}")]
+    #[diagnostic(
+        code(cxx2flow::missing_option),
+        help("set {required} as well, on the command line or in the configuration")
+    )]
+    MissingOption {
+        option: &'static str,
+        required: &'static str,
+    },
+
     #[error("unexpected dummy ast node")]
     #[diagnostic(
         code(cxx2flow::unexpected_dummy_ast),
@@ -131,3 +255,21 @@ pub enum Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Warning about statements of a function which can never run
+#[derive(Debug, Error, Diagnostic)]
+#[error("unreachable code in {function}")]
+#[diagnostic(
+    code(cxx2flow::unreachable_code),
+    severity(Warning),
+    help(
+        "nothing leads here from the begin of the function, \nmaybe a return, break, goto or endless loop comes first"
+    )
+)]
+pub struct UnreachableCode {
+    pub function: String,
+    #[source_code]
+    pub src: NamedSource<String>,
+    #[label(collection, "never runs")]
+    pub ranges: Vec<SourceSpan>,
+}
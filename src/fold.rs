@@ -0,0 +1,248 @@
+//! Folding of deeply nested code into summary statements.
+//!
+//! Loops, ifs and switches nested deeper than a limit are replaced in the [`Ast`] by a single
+//! statement like `for (int i = 0; i < n; ++i) { 14 statements }`, before the graph is built.
+//! The folded code is kept as a [`Region`], which can be charted on its own.
+
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use crate::ast::{Ast, AstNode};
+
+/// Code folded into a summary statement
+pub struct Region {
+    /// 1-based line the folded code starts at
+    pub line: usize,
+    /// The loop, if or switch which was folded
+    pub ast: Rc<RefCell<Ast>>,
+}
+
+fn is_nesting(node: &AstNode) -> bool {
+    matches!(
+        node,
+        AstNode::If { .. }
+            | AstNode::While { .. }
+            | AstNode::DoWhile { .. }
+            | AstNode::For { .. }
+            | AstNode::Switch { .. }
+    )
+}
+
+/// Fold the loops, ifs and switches of `ast` which are nested in `max_depth` others or more,
+/// so that `max_depth` levels are left. The folded regions are returned in document order.
+pub fn fold(ast: &Rc<RefCell<Ast>>, max_depth: usize, source: &str) -> Vec<Region> {
+    let mut regions = Vec::new();
+    fold_node(ast, 0, max_depth, source, &mut regions);
+    regions
+}
+
+/// Fold the code inside a folded `region`, keeping its outermost level, so it can be charted
+/// on its own. Jumps leaving the region are turned into exits.
+pub fn fold_region(region: &Region, max_depth: usize, source: &str) -> Vec<Region> {
+    let mut labels = HashSet::new();
+    collect_labels(&region.ast.borrow(), &mut labels, true, true);
+    close_exits(&region.ast, &labels, false, false);
+    let mut regions = Vec::new();
+    fold_children(&region.ast, 1, max_depth, source, &mut regions);
+    regions
+}
+
+/// `depth` is the number of loops, ifs and switches around `ast`
+fn fold_node(
+    ast: &Rc<RefCell<Ast>>,
+    depth: usize,
+    max_depth: usize,
+    source: &str,
+    regions: &mut Vec<Region>,
+) {
+    if !is_nesting(&ast.borrow().node) {
+        fold_children(ast, depth, max_depth, source, regions);
+        return;
+    }
+    if depth < max_depth {
+        fold_children(ast, depth + 1, max_depth, source, regions);
+        return;
+    }
+    let mut current = ast.borrow_mut();
+    let summary = summarize(&current, source);
+    // gotos and cases from outside may still jump into the folded code, they land on the summary
+    let mut labels = HashSet::new();
+    collect_labels(&current, &mut labels, false, false);
+    let mut labels: Vec<String> = labels.into_iter().collect();
+    labels.sort();
+    if !labels.is_empty() {
+        current.label.get_or_insert_default().extend(labels);
+    }
+    let node = std::mem::replace(&mut current.node, AstNode::Stat(summary));
+    regions.push(Region {
+        line: source[..current.range.start.min(source.len())]
+            .matches('\n')
+            .count()
+            + 1,
+        ast: Rc::new(RefCell::new(Ast::new(node, current.range.clone(), None))),
+    });
+}
+
+/// Fold the statements inside `ast`, which are nested in `depth` loops, ifs and switches
+fn fold_children(
+    ast: &Rc<RefCell<Ast>>,
+    depth: usize,
+    max_depth: usize,
+    source: &str,
+    regions: &mut Vec<Region>,
+) {
+    let current = ast.borrow();
+    match &current.node {
+        AstNode::Compound(v) => {
+            for i in v {
+                fold_node(i, depth, max_depth, source, regions);
+            }
+        }
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            fold_node(body, depth, max_depth, source, regions);
+            if let Some(otherwise) = otherwise {
+                // an `else if` is as deep as the `if` it continues
+                let depth = if matches!(otherwise.borrow().node, AstNode::If { .. }) {
+                    depth - 1
+                } else {
+                    depth
+                };
+                fold_node(otherwise, depth, max_depth, source, regions);
+            }
+        }
+        AstNode::While { body, .. }
+        | AstNode::DoWhile { body, .. }
+        | AstNode::For { body, .. }
+        | AstNode::Switch { body, .. } => fold_node(body, depth, max_depth, source, regions),
+        AstNode::Try { body, handlers } => {
+            fold_node(body, depth, max_depth, source, regions);
+            for (_, handler) in handlers {
+                fold_node(handler, depth, max_depth, source, regions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn count_statements(ast: &Ast) -> usize {
+    let children = |x: &Rc<RefCell<Ast>>| count_statements(&x.borrow());
+    match &ast.node {
+        AstNode::Dummy => 0,
+        AstNode::Compound(v) => v.iter().map(children).sum(),
+        AstNode::If {
+            body, otherwise, ..
+        } => 1 + children(body) + otherwise.as_ref().map_or(0, children),
+        AstNode::While { body, .. }
+        | AstNode::DoWhile { body, .. }
+        | AstNode::For { body, .. }
+        | AstNode::Switch { body, .. } => 1 + children(body),
+        AstNode::Try { body, handlers } => {
+            1 + children(body) + handlers.iter().map(|(_, x)| children(x)).sum::<usize>()
+        }
+        _ => 1,
+    }
+}
+
+fn statements(ast: &Rc<RefCell<Ast>>) -> String {
+    match count_statements(&ast.borrow()) {
+        1 => "{ 1 statement }".to_owned(),
+        n => format!("{{ {n} statements }}"),
+    }
+}
+
+/// `for (int i = 0; i < n; ++i) { 14 statements }`, from the source up to the body
+fn summarize(ast: &Ast, source: &str) -> String {
+    let header = |body: &Rc<RefCell<Ast>>| {
+        source
+            .get(ast.range.start..body.borrow().range.start)
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    match &ast.node {
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            let mut res = format!("{} {}", header(body), statements(body));
+            if let Some(otherwise) = otherwise {
+                res.push_str(format!(" else {}", statements(otherwise)).as_str());
+            }
+            res
+        }
+        AstNode::DoWhile { cond, body, .. } => format!("do {} while {}", statements(body), cond),
+        // the body of a switch spans the switch itself
+        AstNode::Switch { cond, body, .. } => format!("switch {} {}", cond, statements(body)),
+        AstNode::While { body, .. } | AstNode::For { body, .. } => {
+            format!("{} {}", header(body), statements(body))
+        }
+        _ => unreachable!("only loops, ifs and switches are folded"),
+    }
+}
+
+/// Labels of the statements inside `ast`, and those of `ast` itself if `this` is set.
+/// The bodies of switches, with their cases, are only entered if `switches` is set.
+fn collect_labels(ast: &Ast, labels: &mut HashSet<String>, this: bool, switches: bool) {
+    if this && let Some(label) = &ast.label {
+        labels.extend(label.iter().cloned());
+    }
+    let mut children = |x: &Rc<RefCell<Ast>>| collect_labels(&x.borrow(), labels, true, switches);
+    match &ast.node {
+        AstNode::Compound(v) => v.iter().for_each(children),
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            children(body);
+            otherwise.iter().for_each(children);
+        }
+        AstNode::While { body, .. } | AstNode::DoWhile { body, .. } | AstNode::For { body, .. } => {
+            children(body)
+        }
+        AstNode::Switch { body, .. } if switches => children(body),
+        AstNode::Try { body, handlers } => {
+            children(body);
+            handlers.iter().for_each(|(_, x)| children(x));
+        }
+        _ => {}
+    }
+}
+
+/// Turn `break`, `continue` and `goto` leaving a region into statements ending its chart
+fn close_exits(ast: &Rc<RefCell<Ast>>, labels: &HashSet<String>, in_loop: bool, in_switch: bool) {
+    let mut current = ast.borrow_mut();
+    let exit = match &current.node {
+        AstNode::Break(s) if !in_loop && !in_switch => Some(s.clone()),
+        AstNode::Continue(s) if !in_loop => Some(s.clone()),
+        AstNode::Goto(label) if !labels.contains(label) => Some(format!("goto {label};")),
+        _ => None,
+    };
+    if let Some(exit) = exit {
+        current.node = AstNode::Return(exit);
+        return;
+    }
+    let children =
+        |x: &Rc<RefCell<Ast>>, in_loop, in_switch| close_exits(x, labels, in_loop, in_switch);
+    match &current.node {
+        AstNode::Compound(v) => v.iter().for_each(|x| children(x, in_loop, in_switch)),
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            children(body, in_loop, in_switch);
+            otherwise
+                .iter()
+                .for_each(|x| children(x, in_loop, in_switch));
+        }
+        AstNode::While { body, .. } | AstNode::DoWhile { body, .. } | AstNode::For { body, .. } => {
+            children(body, true, in_switch)
+        }
+        AstNode::Switch { body, .. } => children(body, in_loop, true),
+        AstNode::Try { body, handlers } => {
+            children(body, in_loop, in_switch);
+            handlers
+                .iter()
+                .for_each(|(_, x)| children(x, in_loop, in_switch));
+        }
+        _ => {}
+    }
+}
@@ -1,12 +1,14 @@
-use crate::ast::{Ast, AstNode};
+use crate::SwitchMode;
+use crate::ast::{Ast, AstNode, literal_condition};
 use crate::error::{Error, Result};
 use hash_chain::ChainMap;
 use itertools::{Itertools, Position};
 use miette::NamedSource;
 use petgraph::EdgeDirection;
-use petgraph::stable_graph::{NodeIndex, StableDiGraph};
-use petgraph::visit::{EdgeRef, IntoNodeReferences};
-use std::collections::HashMap;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableDiGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences, IntoNodeReferences};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::{cell::RefCell, rc::Rc};
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum GraphNodeType {
@@ -15,43 +17,159 @@ pub enum GraphNodeType {
     Begin,
     End,
     Node(String),
+    /// A statement suspending a coroutine, at `co_await` or `co_yield`
+    Suspend(String),
+    /// Statements running one after another, merged by [`collapse_blocks`]
+    Block(Vec<String>),
     Choice(String),
+    /// The condition heading a loop, deciding whether to run its body once more
+    Loop(String),
+    /// A statement leaving the function, with `return` or `co_return`
+    Return(String),
+    /// A statement jumping elsewhere, with `break`, `continue` or `goto`
+    Jump(String),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GraphNode {
+    pub node_type: GraphNodeType,
+    /// Byte range of the source code this node comes from
+    pub range: Option<Range<usize>>,
+    /// Line and column span of `range`, filled in once the graph is built
+    pub span: Option<Span>,
+    /// Set on code which can never run, when it is to be drawn apart
+    pub unreachable: bool,
+}
+
+impl GraphNode {
+    pub fn new(node_type: GraphNodeType, range: Range<usize>) -> GraphNode {
+        GraphNode {
+            node_type,
+            range: Some(range),
+            span: None,
+            unreachable: false,
+        }
+    }
+}
+
+impl From<GraphNodeType> for GraphNode {
+    fn from(node_type: GraphNodeType) -> Self {
+        GraphNode {
+            node_type,
+            range: None,
+            span: None,
+            unreachable: false,
+        }
+    }
+}
+
+/// 1-based line and column, columns count characters
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Source span of a node, `end` points just past the last character
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    /// GitHub style line anchor, `L3` or `L3-L5`
+    pub fn anchor(&self) -> String {
+        if self.start.line == self.end.line {
+            format!("L{}", self.start.line)
+        } else {
+            format!("L{}-L{}", self.start.line, self.end.line)
+        }
+    }
+}
+
+struct LineIndex<'a> {
+    source: &'a str,
+    /// byte offset of the start of every line
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { source, starts }
+    }
+
+    fn location(&self, offset: usize) -> Location {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|x| *x <= offset);
+        let start = self.starts[line - 1];
+        let column = self
+            .source
+            .get(start..offset)
+            .map_or(offset - start, |x| x.chars().count());
+        Location {
+            line,
+            column: column + 1,
+        }
+    }
+
+    fn span(&self, range: &Range<usize>) -> Span {
+        Span {
+            start: self.location(range.start),
+            end: self.location(range.end),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum EdgeType {
     Normal,
     Branch(bool),
+    /// Catch Parameter
+    Exception(String),
+    /// From the statement defining a lambda to the begin of its flow, with the lambda name
+    Lambda(String),
+    /// Out of a suspension point, where the coroutine goes on once resumed, see [`mark_resumes`]
+    Resume,
+    /// From a multi-way switch to a case, with the values leading there, like `1, 2` or `default`
+    Case(String),
 }
 
-pub type Graph = StableDiGraph<GraphNodeType, EdgeType>;
+pub type Graph = StableDiGraph<GraphNode, EdgeType>;
 
 struct GraphContext {
     pub graph: Graph,
     pub break_target: Option<NodeIndex>,
     pub continue_target: Option<NodeIndex>,
+    /// handlers of the innermost enclosing try block
+    pub catch_target: Option<Vec<(String, NodeIndex)>>,
     pub goto_target: ChainMap<String, NodeIndex>,
-    #[allow(dead_code)]
     pub global_begin: NodeIndex,
     pub global_end: NodeIndex,
     pub local_source: NodeIndex,
     pub local_sink: NodeIndex,
+    pub switches: SwitchMode,
 }
 
 impl GraphContext {
-    fn new() -> GraphContext {
+    fn new(switches: SwitchMode) -> GraphContext {
         let mut graph = Graph::new();
-        let begin = graph.add_node(GraphNodeType::Begin);
-        let end = graph.add_node(GraphNodeType::End);
+        let begin = graph.add_node(GraphNodeType::Begin.into());
+        let end = graph.add_node(GraphNodeType::End.into());
         GraphContext {
             graph,
             break_target: None,
             continue_target: None,
+            catch_target: None,
             goto_target: ChainMap::new(HashMap::new()),
             global_begin: begin,
             global_end: end,
             local_source: begin,
             local_sink: end,
+            switches,
         }
     }
 }
@@ -67,7 +185,7 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             if let Some(v) = context.goto_target.get(i) {
                 context.graph.add_edge(*v, local_source, EdgeType::Normal);
             } else {
-                let v = context.graph.add_node(GraphNodeType::Dummy);
+                let v = context.graph.add_node(GraphNodeType::Dummy.into());
                 context.goto_target.insert_at(0, i.clone(), v)?;
                 // 0 is the global hashmap, goto labels should be put in hashmap 0
                 context.graph.add_edge(v, local_source, EdgeType::Normal);
@@ -82,8 +200,8 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             });
         }
         AstNode::Compound(v) => {
-            let mut sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let mut sub_sink = context.graph.add_node(GraphNodeType::Dummy);
+            let mut sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let mut sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
             context
                 .graph
                 .add_edge(local_source, sub_source, EdgeType::Normal);
@@ -100,7 +218,7 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
                     match pos {
                         itertools::Position::First | itertools::Position::Middle => {
                             sub_source = sub_sink;
-                            sub_sink = context.graph.add_node(GraphNodeType::Dummy);
+                            sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
                         }
                         _ => {}
                     }
@@ -112,9 +230,15 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             context.local_source = local_source;
             context.local_sink = local_sink;
         }
-        AstNode::Stat(s) => {
+        AstNode::Stat(s) | AstNode::Suspend(s) => {
             // local_source -> current -> local_sink
-            let current = context.graph.add_node(GraphNodeType::Node(s.clone()));
+            let node_type = match &ast.node {
+                AstNode::Suspend(_) => GraphNodeType::Suspend(s.clone()),
+                _ => GraphNodeType::Node(s.clone()),
+            };
+            let current = context
+                .graph
+                .add_node(GraphNode::new(node_type, ast.range.clone()));
             context
                 .graph
                 .add_edge(local_source, current, EdgeType::Normal);
@@ -124,7 +248,10 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
         }
         AstNode::Continue(s) => {
             // local_source -> current -> continue_target
-            let current = context.graph.add_node(GraphNodeType::Node(s.clone()));
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Jump(s.clone()),
+                ast.range.clone(),
+            ));
             context
                 .graph
                 .add_edge(local_source, current, EdgeType::Normal);
@@ -139,7 +266,10 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
         }
         AstNode::Break(s) => {
             // local_source -> current -> break_target
-            let current = context.graph.add_node(GraphNodeType::Node(s.clone()));
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Jump(s.clone()),
+                ast.range.clone(),
+            ));
             context
                 .graph
                 .add_edge(local_source, current, EdgeType::Normal);
@@ -154,7 +284,10 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
         }
         AstNode::Return(s) => {
             // local_source -> current -> global_end
-            let current = context.graph.add_node(GraphNodeType::Node(s.clone()));
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Return(s.clone()),
+                ast.range.clone(),
+            ));
             context
                 .graph
                 .add_edge(local_source, current, EdgeType::Normal);
@@ -164,14 +297,18 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
         }
         AstNode::If {
             cond,
+            cond_range,
             body,
             otherwise,
         } => {
             // local_source -> cond -> ---Y--> sub_source -> [...body...] -> sub_sink---------------v
             //                         ---N--> sub_source1 -> Option<[...otherwise...]> -> sub_sink -> local_sink
-            let cond = context.graph.add_node(GraphNodeType::Choice(cond.clone()));
-            let sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let sub_sink = context.graph.add_node(GraphNodeType::Dummy);
+            let cond = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Choice(cond.clone()),
+                cond_range.clone(),
+            ));
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
             context.graph.add_edge(local_source, cond, EdgeType::Normal);
             context
                 .graph
@@ -190,7 +327,7 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             context.local_sink = local_sink;
 
             if let Some(t) = otherwise {
-                let sub_source1 = context.graph.add_node(GraphNodeType::Dummy);
+                let sub_source1 = context.graph.add_node(GraphNodeType::Dummy.into());
                 context
                     .graph
                     .add_edge(cond, sub_source1, EdgeType::Branch(false));
@@ -205,7 +342,11 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
                     .add_edge(cond, local_sink, EdgeType::Branch(false));
             }
         }
-        AstNode::While { cond, body } => {
+        AstNode::While {
+            cond,
+            cond_range,
+            body,
+        } => {
             // local_src -> cond ---Y--> sub_source -> [...body...] -> sub_sink
             //                |  \                                         /
             //                | N \_______________________________________/
@@ -213,9 +354,12 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             //           local_sink
             // continue: jump to cond
             // break: jump to local_sink
-            let cond = context.graph.add_node(GraphNodeType::Choice(cond.clone()));
-            let sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let sub_sink = context.graph.add_node(GraphNodeType::Dummy);
+            let cond = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Loop(cond.clone()),
+                cond_range.clone(),
+            ));
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
             context.graph.add_edge(local_source, cond, EdgeType::Normal);
             context
                 .graph
@@ -234,15 +378,22 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             context.local_source = local_source;
             context.local_sink = local_sink;
         }
-        AstNode::DoWhile { cond, body } => {
+        AstNode::DoWhile {
+            cond,
+            cond_range,
+            body,
+        } => {
             // local_src -> sub_source -> [...body...] -> sub_sink -> cond ---N--> local_sink
             //                    \                                    /
             //                     <-----------------Y----------------<
             // continue: jump to cond
             // break: jump to local_sink
-            let sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let sub_sink = context.graph.add_node(GraphNodeType::Dummy);
-            let cond = context.graph.add_node(GraphNodeType::Choice(cond.clone()));
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
+            let cond = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Loop(cond.clone()),
+                cond_range.clone(),
+            ));
             context
                 .graph
                 .add_edge(local_source, sub_source, EdgeType::Normal);
@@ -265,8 +416,11 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
         }
         AstNode::For {
             init,
+            init_range,
             cond,
+            cond_range,
             upd,
+            upd_range,
             body,
         } => {
             // local_source -> init -> cond ---Y--> sub_source -> [...body...] -> sub_sink -> upd
@@ -276,11 +430,20 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             //                                              <<<
             // continue: jump to sub_sink
             // break: jump to local_sink
-            let sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let sub_sink = context.graph.add_node(GraphNodeType::Dummy);
-            let cond = context.graph.add_node(GraphNodeType::Choice(cond.clone()));
-            let init = context.graph.add_node(GraphNodeType::Node(init.clone()));
-            let upd = context.graph.add_node(GraphNodeType::Node(upd.clone()));
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
+            let cond = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Loop(cond.clone()),
+                cond_range.clone(),
+            ));
+            let init = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Node(init.clone()),
+                init_range.clone(),
+            ));
+            let upd = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Node(upd.clone()),
+                upd_range.clone(),
+            ));
             context.graph.add_edge(local_source, init, EdgeType::Normal);
             context.graph.add_edge(init, cond, EdgeType::Normal);
             context
@@ -301,7 +464,12 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             context.local_source = local_source;
             context.local_sink = local_sink;
         }
-        AstNode::Switch { cond, body, cases } => {
+        AstNode::Switch {
+            cond,
+            cond_range,
+            body,
+            cases,
+        } => {
             // local_src -> cond == case[0] ---Y-> goto case[0]
             //                              ---N-> cond == case[1] ....
             //                                                ---N--> goto default
@@ -310,21 +478,38 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             // break: local_sink
             let case_goto_targets: HashMap<String, NodeIndex> = cases
                 .iter()
-                .map(|c| (c.clone(), context.graph.add_node(GraphNodeType::Dummy)))
+                .map(|c| {
+                    (
+                        c.clone(),
+                        context.graph.add_node(GraphNodeType::Dummy.into()),
+                    )
+                })
                 .collect();
-            let table_start = generate_jump_table(
-                cond,
-                &mut context.graph,
-                &mut cases.iter().filter(|x| *x != "default").with_position(),
-                &case_goto_targets,
-                &cases.iter().any(|x| x == "default"),
-                &local_sink,
-            );
+            let table_start = match context.switches {
+                SwitchMode::Ladder => generate_jump_table(
+                    cond,
+                    &mut context.graph,
+                    &mut cases.iter().filter(|x| *x != "default").with_position(),
+                    &case_goto_targets,
+                    &cases.iter().any(|x| x == "default"),
+                    &local_sink,
+                    cond_range,
+                ),
+                SwitchMode::MultiWay => generate_multi_way(
+                    cond,
+                    &mut context.graph,
+                    cases,
+                    &case_groups(cases, &body.borrow()),
+                    &case_goto_targets,
+                    &local_sink,
+                    cond_range,
+                ),
+            };
             context
                 .graph
                 .add_edge(local_source, table_start, EdgeType::Normal);
-            let sub_source = context.graph.add_node(GraphNodeType::Dummy);
-            let sub_sink = context.graph.add_node(GraphNodeType::Dummy);
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            let sub_sink = context.graph.add_node(GraphNodeType::Dummy.into());
             context.goto_target.new_child_with(case_goto_targets);
             context.local_source = sub_source;
             context.local_sink = sub_sink;
@@ -340,16 +525,84 @@ fn build_graph(ast: &Ast, context: &mut GraphContext, source: &str, file_name: &
             context.continue_target = continue_target;
             context.goto_target.remove_child();
         }
-        AstNode::Goto(t) => {
-            // local_source -> goto_target
-            if let Some(target) = context.goto_target.get(t) {
+        AstNode::Try { body, handlers } => {
+            // local_source -> try -> sub_source -> [...body...] -> local_sink
+            //                   \---E--> handler[i] -> [...handler...] -> local_sink
+            // throw: jump to handler[i] inside body, to the outer handlers inside handler
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Node("try".to_string()),
+                ast.range.clone(),
+            ));
+            let sub_source = context.graph.add_node(GraphNodeType::Dummy.into());
+            context
+                .graph
+                .add_edge(local_source, current, EdgeType::Normal);
+            context
+                .graph
+                .add_edge(current, sub_source, EdgeType::Normal);
+            let catch_target = context.catch_target.take();
+            let mut targets = Vec::new();
+            for (param, handler) in handlers {
+                let handler_source = context.graph.add_node(GraphNodeType::Dummy.into());
                 context
                     .graph
-                    .add_edge(local_source, *target, EdgeType::Normal);
+                    .add_edge(current, handler_source, EdgeType::Exception(param.clone()));
+                context.catch_target = catch_target.clone();
+                context.local_source = handler_source;
+                context.local_sink = local_sink;
+                build_graph(&handler.borrow(), context, source, file_name)?;
+                targets.push((param.clone(), handler_source));
+            }
+            context.catch_target = Some(targets);
+            context.local_source = sub_source;
+            context.local_sink = local_sink;
+            build_graph(&body.borrow(), context, source, file_name)?;
+            context.catch_target = catch_target;
+            context.local_source = local_source;
+            context.local_sink = local_sink;
+        }
+        AstNode::Throw(s) => {
+            // local_source -> current ---E--> catch_target
+            //                         \-----> global_end (uncaught)
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Node(s.clone()),
+                ast.range.clone(),
+            ));
+            context
+                .graph
+                .add_edge(local_source, current, EdgeType::Normal);
+            match &context.catch_target {
+                Some(targets) => {
+                    for (param, target) in targets {
+                        context.graph.add_edge(
+                            current,
+                            *target,
+                            EdgeType::Exception(param.clone()),
+                        );
+                    }
+                }
+                None => {
+                    context
+                        .graph
+                        .add_edge(current, context.global_end, EdgeType::Normal);
+                }
+            }
+        }
+        AstNode::Goto(t) => {
+            // local_source -> current -> goto_target
+            let current = context.graph.add_node(GraphNode::new(
+                GraphNodeType::Jump(format!("goto {t};")),
+                ast.range.clone(),
+            ));
+            context
+                .graph
+                .add_edge(local_source, current, EdgeType::Normal);
+            if let Some(target) = context.goto_target.get(t) {
+                context.graph.add_edge(current, *target, EdgeType::Normal);
             } else {
-                let v = context.graph.add_node(GraphNodeType::Dummy);
+                let v = context.graph.add_node(GraphNodeType::Dummy.into());
                 context.goto_target.insert_at(0, t.clone(), v)?;
-                context.graph.add_edge(local_source, v, EdgeType::Normal);
+                context.graph.add_edge(current, v, EdgeType::Normal);
             }
         }
     }
@@ -363,6 +616,7 @@ fn generate_jump_table<I, R>(
     case_goto_targets: &HashMap<String, NodeIndex>,
     has_default: &bool,
     sink: &NodeIndex,
+    range: &Range<usize>,
 ) -> NodeIndex
 where
     I: Itertools<Item = (Position, R)>,
@@ -370,12 +624,22 @@ where
 {
     if let Some((pos, i)) = iter.next() {
         // dbg!(i);
-        let cur = graph.add_node(GraphNodeType::Choice(format!("{} == {}", cond, i.as_ref())));
+        let cur = graph.add_node(GraphNode::new(
+            GraphNodeType::Choice(format!("{} == {}", cond, i.as_ref())),
+            range.clone(),
+        ));
         graph.add_edge(cur, case_goto_targets[i.as_ref()], EdgeType::Branch(true));
         match pos {
             itertools::Position::First | itertools::Position::Middle => {
-                let idx =
-                    generate_jump_table(cond, graph, iter, case_goto_targets, has_default, sink);
+                let idx = generate_jump_table(
+                    cond,
+                    graph,
+                    iter,
+                    case_goto_targets,
+                    has_default,
+                    sink,
+                    range,
+                );
                 graph.add_edge(cur, idx, EdgeType::Branch(false));
             }
             itertools::Position::Last | itertools::Position::Only => {
@@ -388,7 +652,7 @@ where
         };
         cur
     } else {
-        let cur = graph.add_node(GraphNodeType::Dummy);
+        let cur = graph.add_node(GraphNodeType::Dummy.into());
         if *has_default {
             graph.add_edge(cur, case_goto_targets["default"], EdgeType::Normal);
         } else {
@@ -398,11 +662,95 @@ where
     }
 }
 
+/// Cases of a switch, grouped by the statement they lead to
+fn case_groups(cases: &[String], body: &Ast) -> Vec<Vec<String>> {
+    let mut res = Vec::new();
+    collect_case_groups(cases, body, &mut res);
+    res
+}
+
+/// Groups of cases labeling `ast` and the statements nested into it, apart from inner switches
+fn collect_case_groups(cases: &[String], ast: &Ast, res: &mut Vec<Vec<String>>) {
+    if let Some(labels) = &ast.label {
+        let group: Vec<String> = labels
+            .iter()
+            .filter(|x| cases.contains(x))
+            .cloned()
+            .collect();
+        if !group.is_empty() {
+            res.push(group);
+        }
+    }
+    match &ast.node {
+        AstNode::Compound(v) => {
+            for i in v {
+                collect_case_groups(cases, &i.borrow(), res);
+            }
+        }
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            collect_case_groups(cases, &body.borrow(), res);
+            if let Some(otherwise) = otherwise {
+                collect_case_groups(cases, &otherwise.borrow(), res);
+            }
+        }
+        AstNode::While { body, .. } | AstNode::DoWhile { body, .. } | AstNode::For { body, .. } => {
+            collect_case_groups(cases, &body.borrow(), res)
+        }
+        AstNode::Try { body, handlers } => {
+            collect_case_groups(cases, &body.borrow(), res);
+            for (_, handler) in handlers {
+                collect_case_groups(cases, &handler.borrow(), res);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// One decision with an edge to every group of cases, the other targets of a group are
+/// left without incoming edges and get removed with the dummy nodes
+fn generate_multi_way(
+    cond: &str,
+    graph: &mut Graph,
+    cases: &[String],
+    groups: &[Vec<String>],
+    case_goto_targets: &HashMap<String, NodeIndex>,
+    sink: &NodeIndex,
+    range: &Range<usize>,
+) -> NodeIndex {
+    let cur = graph.add_node(GraphNode::new(
+        GraphNodeType::Choice(cond.to_owned()),
+        range.clone(),
+    ));
+    for group in groups {
+        graph.add_edge(
+            cur,
+            case_goto_targets[&group[0]],
+            EdgeType::Case(group.join(", ")),
+        );
+    }
+    // trailing cases without a statement leave the switch, like values without a case
+    let grouped: HashSet<&String> = groups.iter().flatten().collect();
+    let mut rest: Vec<&str> = cases
+        .iter()
+        .filter(|x| !grouped.contains(x))
+        .map(String::as_str)
+        .collect();
+    if !cases.iter().any(|x| x == "default") {
+        rest.push("default");
+    }
+    if !rest.is_empty() {
+        graph.add_edge(cur, *sink, EdgeType::Case(rest.join(", ")));
+    }
+    cur
+}
+
 fn remove_zero_in_degree_nodes(graph: &mut Graph, _source: &str) -> bool {
     let nodes = graph
         .node_indices()
         .filter(|i| -> bool {
-            *graph.node_weight(*i).unwrap() == GraphNodeType::Dummy
+            graph.node_weight(*i).unwrap().node_type == GraphNodeType::Dummy
                 && graph.edges_directed(*i, EdgeDirection::Incoming).count() == 0
         })
         .collect_vec();
@@ -423,14 +771,14 @@ where
     // take first dummy node
     if let Some(node_index) = graph
         .node_references()
-        .filter(|(x, t)| predicate(*x, t))
+        .filter(|(x, t)| predicate(*x, &t.node_type))
         .map(|(x, _)| x)
         .take(1)
         .next()
     {
         let incoming_edges = graph
             .edges_directed(node_index, EdgeDirection::Incoming)
-            .map(|x| (x.source(), *x.weight()))
+            .map(|x| (x.source(), x.weight().clone()))
             .collect_vec();
         let neighbors = graph
             .neighbors_directed(node_index, EdgeDirection::Outgoing)
@@ -455,8 +803,22 @@ where
 }
 
 pub fn from_ast(ast: Rc<RefCell<Ast>>, source: &str, file_name: &str) -> Result<Graph> {
-    let mut ctx = GraphContext::new();
+    from_ast_with_switches(ast, source, file_name, SwitchMode::default())
+}
+
+/// Like [`from_ast`], drawing switches as set by `switches`
+pub fn from_ast_with_switches(
+    ast: Rc<RefCell<Ast>>,
+    source: &str,
+    file_name: &str,
+    switches: SwitchMode,
+) -> Result<Graph> {
+    let mut ctx = GraphContext::new(switches);
     build_graph(&ast.borrow(), &mut ctx, source, file_name)?;
+    // begin and end stand for the whole function body
+    let range = ast.borrow().range.clone();
+    ctx.graph[ctx.global_begin].range = Some(range.clone());
+    ctx.graph[ctx.global_end].range = Some(range);
     // dbg!(petgraph::dot::Dot::new(&ctx.graph));
     while remove_zero_in_degree_nodes(&mut ctx.graph, source) {}
     while remove_single_node(&mut ctx.graph, source, |_, t| *t == GraphNodeType::Dummy)? {}
@@ -465,5 +827,245 @@ pub fn from_ast(ast: Rc<RefCell<Ast>>, source: &str, file_name: &str) -> Result<
         _ => false,
     };
     while remove_single_node(&mut ctx.graph, source, remove_empty_nodes)? {}
+    let lines = LineIndex::new(source);
+    for node in ctx.graph.node_weights_mut() {
+        node.span = node.range.as_ref().map(|x| lines.span(x));
+    }
     Ok(ctx.graph)
 }
+
+/// Branches of choices with a literal condition which are never taken
+fn dead_branches(graph: &Graph) -> Vec<EdgeIndex> {
+    graph
+        .edge_references()
+        .filter(|x| match (&graph[x.source()].node_type, x.weight()) {
+            (GraphNodeType::Choice(cond) | GraphNodeType::Loop(cond), EdgeType::Branch(taken)) => {
+                literal_condition(cond).is_some_and(|x| x != *taken)
+            }
+            _ => false,
+        })
+        .map(|x| x.id())
+        .collect()
+}
+
+/// Nodes which cannot be reached from the begin of the flow, leaving out dead branches
+pub fn unreachable(graph: &Graph) -> Vec<NodeIndex> {
+    let dead: HashSet<EdgeIndex> = dead_branches(graph).into_iter().collect();
+    let mut stack = graph
+        .node_references()
+        .filter(|(id, x)| {
+            x.node_type == GraphNodeType::Begin
+                && graph
+                    .edges_directed(*id, EdgeDirection::Incoming)
+                    .next()
+                    .is_none()
+        })
+        .map(|(id, _)| id)
+        .collect_vec();
+    let mut reached: HashSet<NodeIndex> = stack.iter().copied().collect();
+    while let Some(node) = stack.pop() {
+        for edge in graph.edges(node) {
+            if !dead.contains(&edge.id()) && reached.insert(edge.target()) {
+                stack.push(edge.target());
+            }
+        }
+    }
+    graph
+        .node_indices()
+        .filter(|x| !reached.contains(x))
+        .collect()
+}
+
+/// Remove dead branches and every node which cannot be reached
+pub fn remove_dead_code(graph: &mut Graph) {
+    for node in unreachable(graph) {
+        graph.remove_node(node);
+    }
+    for edge in dead_branches(graph) {
+        graph.remove_edge(edge);
+    }
+}
+
+/// Give every return an end node of its own, rather than leading them all to the end of the
+/// function, which is left out once nothing else reaches it
+pub fn separate_returns(graph: &mut Graph) {
+    let returns = graph
+        .edge_references()
+        .filter(|x| {
+            matches!(graph[x.source()].node_type, GraphNodeType::Return(_))
+                && graph[x.target()].node_type == GraphNodeType::End
+        })
+        .map(|x| (x.id(), x.source(), x.target()))
+        .collect_vec();
+    for (edge, source, end) in returns {
+        let node = GraphNode {
+            unreachable: graph[source].unreachable,
+            ..graph[end].clone()
+        };
+        let own_end = graph.add_node(node);
+        graph.remove_edge(edge);
+        graph.add_edge(source, own_end, EdgeType::Normal);
+        if graph
+            .edges_directed(end, EdgeDirection::Incoming)
+            .next()
+            .is_none()
+        {
+            graph.remove_node(end);
+        }
+    }
+}
+
+/// Mark the edges leaving suspension points as the way the coroutine resumes
+pub fn mark_resumes(graph: &mut Graph) {
+    let edges: Vec<EdgeIndex> = graph
+        .edge_references()
+        .filter(|x| {
+            matches!(graph[x.source()].node_type, GraphNodeType::Suspend(_))
+                && matches!(x.weight(), EdgeType::Normal)
+        })
+        .map(|x| x.id())
+        .collect();
+    for edge in edges {
+        graph[edge] = EdgeType::Resume;
+    }
+}
+
+fn statements(node_type: &GraphNodeType) -> Option<&[String]> {
+    match node_type {
+        GraphNodeType::Node(s) => Some(std::slice::from_ref(s)),
+        GraphNodeType::Block(v) => Some(v),
+        _ => None,
+    }
+}
+
+fn line_count(statements: &[String]) -> usize {
+    statements.iter().map(|x| x.lines().count().max(1)).sum()
+}
+
+/// Whether `head` only goes on to `next`, and `next` can only be reached from `head`
+fn chained(graph: &Graph, head: NodeIndex, next: NodeIndex) -> bool {
+    head != next
+        && statements(&graph[head].node_type).is_some()
+        && statements(&graph[next].node_type).is_some()
+        && graph.edges(head).count() == 1
+        && graph.edges_directed(next, EdgeDirection::Incoming).count() == 1
+}
+
+/// Append the statements of `next` to `head`, which takes over its outgoing edges
+fn merge(graph: &mut Graph, head: NodeIndex, next: NodeIndex) {
+    let next_node = graph[next].clone();
+    let mut merged = statements(&graph[head].node_type).unwrap().to_vec();
+    merged.extend_from_slice(statements(&next_node.node_type).unwrap());
+    let node = &mut graph[head];
+    node.node_type = GraphNodeType::Block(merged);
+    // a for loop update comes after the body in the source, but before the condition in the
+    // chain, so the block may start before its head or end before `next`
+    if let (Some(range), Some(next_range)) = (&mut node.range, &next_node.range) {
+        if next_range.start < range.start {
+            range.start = next_range.start;
+            node.span = node.span.zip(next_node.span).map(|(x, y)| Span {
+                start: y.start,
+                end: x.end,
+            });
+        }
+        if next_range.end > range.end {
+            range.end = next_range.end;
+            node.span = node.span.zip(next_node.span).map(|(x, y)| Span {
+                start: x.start,
+                end: y.end,
+            });
+        }
+    }
+    let outgoing = graph
+        .edges(next)
+        .map(|x| (x.target(), x.weight().clone()))
+        .collect_vec();
+    for (target, weight) in outgoing {
+        graph.add_edge(head, target, weight);
+    }
+    graph.remove_node(next);
+}
+
+/// Merge maximal chains of statements without branches into basic blocks,
+/// each of at most `max_lines` lines.
+pub fn collapse_blocks(graph: &mut Graph, max_lines: Option<usize>) {
+    let next: HashMap<NodeIndex, NodeIndex> = graph
+        .edge_references()
+        .filter(|x| {
+            matches!(x.weight(), EdgeType::Normal) && chained(graph, x.source(), x.target())
+        })
+        .map(|x| (x.source(), x.target()))
+        .collect();
+    let followers: HashSet<NodeIndex> = next.values().copied().collect();
+    // chains start where nothing leads into them, except for chains closed into a loop
+    let starts = next
+        .keys()
+        .copied()
+        .sorted()
+        .sorted_by_key(|x| followers.contains(x))
+        .collect_vec();
+    let mut visited = HashSet::new();
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        let (mut head, mut current) = (start, start);
+        let mut lines = line_count(statements(&graph[head].node_type).unwrap());
+        while let Some(&following) = next.get(&current) {
+            if !visited.insert(following) {
+                break;
+            }
+            let following_lines = line_count(statements(&graph[following].node_type).unwrap());
+            if max_lines.is_some_and(|x| lines + following_lines > x) {
+                head = following;
+                lines = following_lines;
+            } else {
+                merge(graph, head, following);
+                lines += following_lines;
+            }
+            current = following;
+        }
+    }
+}
+
+/// Copy the flow of a lambda into `graph`, linked from the innermost node containing `range`
+pub fn nest(graph: &mut Graph, name: &str, range: &Range<usize>, lambda: &Graph) {
+    let parent = graph
+        .node_references()
+        .filter(|(_, x)| {
+            matches!(
+                x.node_type,
+                GraphNodeType::Node(_)
+                    | GraphNodeType::Suspend(_)
+                    | GraphNodeType::Return(_)
+                    | GraphNodeType::Jump(_)
+                    | GraphNodeType::Block(_)
+                    | GraphNodeType::Choice(_)
+                    | GraphNodeType::Loop(_)
+            ) && x
+                .range
+                .as_ref()
+                .is_some_and(|x| x.start <= range.start && range.end <= x.end)
+        })
+        .min_by_key(|(_, x)| x.range.as_ref().map(|x| x.len()))
+        .map(|(id, _)| id);
+    let mut index = HashMap::new();
+    let mut begin = None;
+    for (id, node) in lambda.node_references() {
+        let new_id = graph.add_node(node.clone());
+        if node.node_type == GraphNodeType::Begin {
+            begin = Some(new_id);
+        }
+        index.insert(id, new_id);
+    }
+    for edge in lambda.edge_references() {
+        graph.add_edge(
+            index[&edge.source()],
+            index[&edge.target()],
+            edge.weight().clone(),
+        );
+    }
+    if let (Some(parent), Some(begin)) = (parent, begin) {
+        graph.add_edge(parent, begin, EdgeType::Lambda(name.to_owned()));
+    }
+}
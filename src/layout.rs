@@ -0,0 +1,460 @@
+//! Layered (Sugiyama-style) layout for flow graphs.
+//!
+//! Nodes are ranked top to bottom, ordered inside each rank to reduce edge
+//! crossings, and edges are routed orthogonally. Edges pointing upwards
+//! (loops, backward gotos) are routed around the right side of the chart.
+
+const RANK_SEP: f64 = 40.0;
+const NODE_SEP: f64 = 30.0;
+const LANE_SEP: f64 = 12.0;
+const MARGIN: f64 = 20.0;
+const SWEEPS: usize = 8;
+const SMOOTHING: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Size {
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Position of a node, `x` and `y` are the center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    fn top(&self) -> f64 {
+        self.y - self.height / 2.0
+    }
+    fn bottom(&self) -> f64 {
+        self.y + self.height / 2.0
+    }
+    fn right(&self) -> f64 {
+        self.x + self.width / 2.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub nodes: Vec<Rect>,
+    /// Orthogonal polyline of every edge, from source to target
+    pub edges: Vec<Vec<Point>>,
+    /// Where the label of every edge should be placed
+    pub labels: Vec<Point>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// mark edges which close a cycle, found by depth first search from `roots`
+fn find_back_edges(n: usize, edges: &[(usize, usize)], roots: &[usize]) -> Vec<bool> {
+    let mut out: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (idx, (u, _)) in edges.iter().enumerate() {
+        out[*u].push(idx);
+    }
+    // 0: unvisited, 1: on stack, 2: done
+    let mut state = vec![0u8; n];
+    let mut back = vec![false; edges.len()];
+    for root in roots.iter().copied().chain(0..n) {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+        while let Some((u, i)) = stack.pop() {
+            if i < out[u].len() {
+                stack.push((u, i + 1));
+                let e = out[u][i];
+                let v = edges[e].1;
+                match state[v] {
+                    0 => {
+                        state[v] = 1;
+                        stack.push((v, 0));
+                    }
+                    1 => back[e] = true,
+                    _ => {}
+                }
+            } else {
+                state[u] = 2;
+            }
+        }
+    }
+    back
+}
+
+/// longest path ranking over the acyclic part of the graph
+fn assign_ranks(n: usize, forward: &[(usize, usize)], sink: Option<usize>) -> Vec<usize> {
+    let mut in_degree = vec![0usize; n];
+    let mut out: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (u, v) in forward {
+        in_degree[*v] += 1;
+        out[*u].push(*v);
+    }
+    let mut rank = vec![0usize; n];
+    let mut queue: Vec<usize> = (0..n).filter(|x| in_degree[*x] == 0).collect();
+    while let Some(u) = queue.pop() {
+        for v in &out[u] {
+            rank[*v] = rank[*v].max(rank[u] + 1);
+            in_degree[*v] -= 1;
+            if in_degree[*v] == 0 {
+                queue.push(*v);
+            }
+        }
+    }
+    if let Some(sink) = sink {
+        // the end node always sits on the last rank alone
+        let max_rank = (0..n)
+            .filter(|x| *x != sink)
+            .map(|x| rank[x])
+            .max()
+            .unwrap_or(0);
+        rank[sink] = max_rank + 1;
+    }
+    rank
+}
+
+fn count_crossings(upper: &[usize], lower: &[usize], pos: &[usize], out: &[Vec<usize>]) -> usize {
+    let mut pairs = Vec::new();
+    for u in upper {
+        for v in &out[*u] {
+            if lower.contains(v) {
+                pairs.push((pos[*u], pos[*v]));
+            }
+        }
+    }
+    let mut res = 0;
+    for (i, (a1, b1)) in pairs.iter().enumerate() {
+        for (a2, b2) in &pairs[i + 1..] {
+            if (a1 < a2 && b1 > b2) || (a1 > a2 && b1 < b2) {
+                res += 1;
+            }
+        }
+    }
+    res
+}
+
+fn total_crossings(layers: &[Vec<usize>], pos: &[usize], out: &[Vec<usize>]) -> usize {
+    layers
+        .windows(2)
+        .map(|w| count_crossings(&w[0], &w[1], pos, out))
+        .sum()
+}
+
+fn update_positions(layers: &[Vec<usize>], pos: &mut [usize]) {
+    for layer in layers {
+        for (i, v) in layer.iter().enumerate() {
+            pos[*v] = i;
+        }
+    }
+}
+
+/// reorder `layer` by the barycenter of its neighbors in the adjacent layer
+fn barycenter_sort(layer: &mut [usize], neighbors: &[Vec<usize>], pos: &[usize]) {
+    let mut keyed: Vec<(f64, usize)> = layer
+        .iter()
+        .map(|v| {
+            let adj = &neighbors[*v];
+            let key = if adj.is_empty() {
+                pos[*v] as f64
+            } else {
+                adj.iter().map(|x| pos[*x] as f64).sum::<f64>() / adj.len() as f64
+            };
+            (key, *v)
+        })
+        .collect();
+    // stable sort keeps the previous order on ties
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (slot, (_, v)) in layer.iter_mut().zip(keyed) {
+        *slot = v;
+    }
+}
+
+/// place the nodes of one layer as close to `desired` as possible, keeping their order
+fn place_layer(layer: &[usize], desired: &[f64], sizes: &[Size], x: &mut [f64]) {
+    if layer.is_empty() {
+        return;
+    }
+    let sep = |a: usize, b: usize| (sizes[a].width + sizes[b].width) / 2.0 + NODE_SEP;
+    let mut left = desired.to_vec();
+    for i in 1..layer.len() {
+        left[i] = left[i].max(left[i - 1] + sep(layer[i - 1], layer[i]));
+    }
+    let mut right = desired.to_vec();
+    for i in (0..layer.len() - 1).rev() {
+        right[i] = right[i].min(right[i + 1] - sep(layer[i], layer[i + 1]));
+    }
+    // both placements keep the separation, so does their average
+    for (i, v) in layer.iter().enumerate() {
+        x[*v] = (left[i] + right[i]) / 2.0;
+    }
+}
+
+pub fn layout(
+    sizes: &[Size],
+    edges: &[(usize, usize)],
+    source: Option<usize>,
+    sink: Option<usize>,
+) -> Layout {
+    let n = sizes.len();
+    let roots: Vec<usize> = source.into_iter().collect();
+    let back = find_back_edges(n, edges, &roots);
+    let forward: Vec<(usize, usize)> = edges
+        .iter()
+        .zip(&back)
+        .filter(|((u, v), b)| !**b && u != v)
+        .map(|(e, _)| *e)
+        .collect();
+    let rank = assign_ranks(n, &forward, sink);
+
+    // split long forward edges with virtual nodes of zero size
+    let mut sizes = sizes.to_vec();
+    let mut rank = rank;
+    let mut paths: Vec<Vec<usize>> = Vec::with_capacity(edges.len());
+    for (idx, (u, v)) in edges.iter().enumerate() {
+        if back[idx] || u == v {
+            paths.push(vec![*u, *v]);
+            continue;
+        }
+        let mut path = vec![*u];
+        for r in rank[*u] + 1..rank[*v] {
+            sizes.push(Size {
+                width: 0.0,
+                height: 0.0,
+            });
+            rank.push(r);
+            path.push(sizes.len() - 1);
+        }
+        path.push(*v);
+        paths.push(path);
+    }
+    let total = sizes.len();
+    let mut out: Vec<Vec<usize>> = vec![Vec::new(); total];
+    let mut inc: Vec<Vec<usize>> = vec![Vec::new(); total];
+    for (idx, path) in paths.iter().enumerate() {
+        if back[idx] || path[0] == path[1] {
+            continue;
+        }
+        for w in path.windows(2) {
+            out[w[0]].push(w[1]);
+            inc[w[1]].push(w[0]);
+        }
+    }
+
+    let layer_count = rank.iter().max().map(|x| x + 1).unwrap_or(0);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    for v in 0..total {
+        layers[rank[v]].push(v);
+    }
+
+    // crossing reduction
+    let mut pos = vec![0usize; total];
+    update_positions(&layers, &mut pos);
+    let mut best = layers.clone();
+    let mut best_crossings = total_crossings(&layers, &pos, &out);
+    for _ in 0..SWEEPS {
+        if best_crossings == 0 {
+            break;
+        }
+        for i in 1..layers.len() {
+            barycenter_sort(&mut layers[i], &inc, &pos);
+            update_positions(&layers, &mut pos);
+        }
+        for i in (0..layers.len().saturating_sub(1)).rev() {
+            barycenter_sort(&mut layers[i], &out, &pos);
+            update_positions(&layers, &mut pos);
+        }
+        let crossings = total_crossings(&layers, &pos, &out);
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best = layers.clone();
+        }
+    }
+    let layers = best;
+
+    // coordinate assignment
+    let mut x = vec![0.0; total];
+    for layer in &layers {
+        place_layer(layer, &vec![0.0; layer.len()], &sizes, &mut x);
+    }
+    for _ in 0..SMOOTHING {
+        for (i, layer) in layers.iter().enumerate().skip(1) {
+            let desired: Vec<f64> = layer
+                .iter()
+                .map(|v| match inc[*v].is_empty() {
+                    true => x[*v],
+                    false => inc[*v].iter().map(|u| x[*u]).sum::<f64>() / inc[*v].len() as f64,
+                })
+                .collect();
+            place_layer(&layers[i], &desired, &sizes, &mut x);
+        }
+        for layer in layers.iter().rev().skip(1) {
+            let desired: Vec<f64> = layer
+                .iter()
+                .map(|v| match out[*v].is_empty() {
+                    true => x[*v],
+                    false => out[*v].iter().map(|u| x[*u]).sum::<f64>() / out[*v].len() as f64,
+                })
+                .collect();
+            place_layer(layer, &desired, &sizes, &mut x);
+        }
+    }
+    let min_x = (0..total)
+        .map(|v| x[v] - sizes[v].width / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    let shift = if min_x.is_finite() {
+        MARGIN - min_x
+    } else {
+        0.0
+    };
+    x.iter_mut().for_each(|v| *v += shift);
+
+    let mut layer_y = Vec::with_capacity(layers.len());
+    let mut layer_height = Vec::with_capacity(layers.len());
+    let mut y = MARGIN;
+    for layer in &layers {
+        let h = layer.iter().map(|v| sizes[*v].height).fold(0.0, f64::max);
+        layer_y.push(y + h / 2.0);
+        layer_height.push(h);
+        y += h + RANK_SEP;
+    }
+    let height = y - RANK_SEP + MARGIN;
+    let rects: Vec<Rect> = (0..total)
+        .map(|v| Rect {
+            x: x[v],
+            y: layer_y[rank[v]],
+            width: sizes[v].width,
+            height: sizes[v].height,
+        })
+        .collect();
+    // middle of the gap below rank r
+    let gap_below = |r: usize| layer_y[r] + layer_height[r] / 2.0 + RANK_SEP / 2.0;
+    let gap_above = |r: usize| layer_y[r] - layer_height[r] / 2.0 - RANK_SEP / 2.0;
+    let mut width = rects.iter().map(|r| r.right()).fold(0.0, f64::max) + MARGIN;
+
+    // edge routing
+    let mut routes = Vec::with_capacity(edges.len());
+    let mut labels = Vec::with_capacity(edges.len());
+    let mut lanes: Vec<(usize, usize, f64)> = Vec::new();
+    let mut back_order: Vec<usize> = (0..edges.len())
+        .filter(|e| back[*e] && edges[*e].0 != edges[*e].1)
+        .collect();
+    // inner loops get the lanes closer to the chart
+    back_order.sort_by_key(|e| {
+        let (u, v) = edges[*e];
+        (rank[u].abs_diff(rank[v]), *e)
+    });
+    let mut back_lane = vec![0.0; edges.len()];
+    for e in back_order {
+        let (u, v) = edges[e];
+        let (lo, hi) = (rank[u].min(rank[v]), rank[u].max(rank[v]));
+        let mut lane = (0..total)
+            .filter(|w| (lo..=hi).contains(&rank[*w]))
+            .map(|w| rects[w].right())
+            .fold(0.0, f64::max)
+            + LANE_SEP;
+        for (l, h, x) in &lanes {
+            if *l <= hi && lo <= *h {
+                lane = lane.max(x + LANE_SEP);
+            }
+        }
+        lanes.push((lo, hi, lane));
+        back_lane[e] = lane;
+        width = width.max(lane + MARGIN);
+    }
+    for (idx, path) in paths.iter().enumerate() {
+        let (u, v) = edges[idx];
+        let (src, dst) = (rects[u], rects[v]);
+        let mut points = Vec::new();
+        if u == v {
+            let right = src.right();
+            points.push(Point {
+                x: right,
+                y: src.y - 5.0,
+            });
+            points.push(Point {
+                x: right + LANE_SEP,
+                y: src.y - 5.0,
+            });
+            points.push(Point {
+                x: right + LANE_SEP,
+                y: src.y + 5.0,
+            });
+            points.push(Point {
+                x: right,
+                y: src.y + 5.0,
+            });
+            width = width.max(right + LANE_SEP + MARGIN);
+            labels.push(Point {
+                x: right + LANE_SEP + 4.0,
+                y: src.y,
+            });
+        } else if back[idx] {
+            let lane = back_lane[idx];
+            let below = gap_below(rank[u]);
+            let above = gap_above(rank[v]);
+            points.push(Point {
+                x: src.x,
+                y: src.bottom(),
+            });
+            points.push(Point { x: src.x, y: below });
+            points.push(Point { x: lane, y: below });
+            points.push(Point { x: lane, y: above });
+            points.push(Point { x: dst.x, y: above });
+            points.push(Point {
+                x: dst.x,
+                y: dst.top(),
+            });
+            labels.push(Point {
+                x: src.x + 4.0,
+                y: below - 4.0,
+            });
+        } else {
+            points.push(Point {
+                x: src.x,
+                y: src.bottom(),
+            });
+            for w in path.windows(2) {
+                let (a, b) = (rects[w[0]], rects[w[1]]);
+                let mid = gap_below(rank[w[0]]);
+                points.push(Point { x: a.x, y: mid });
+                points.push(Point { x: b.x, y: mid });
+                points.push(Point { x: b.x, y: b.top() });
+            }
+            let first_turn = points[2];
+            labels.push(Point {
+                x: first_turn.x + 4.0,
+                y: first_turn.y + 12.0,
+            });
+        }
+        points.dedup();
+        // drop points in the middle of straight segments
+        let mut simplified: Vec<Point> = Vec::with_capacity(points.len());
+        for p in points {
+            if simplified.len() >= 2 {
+                let a = simplified[simplified.len() - 2];
+                let b = simplified[simplified.len() - 1];
+                if (a.x == b.x && b.x == p.x) || (a.y == b.y && b.y == p.y) {
+                    simplified.pop();
+                }
+            }
+            simplified.push(p);
+        }
+        routes.push(simplified);
+    }
+    Layout {
+        nodes: rects[..n].to_vec(),
+        edges: routes,
+        labels,
+        width,
+        height,
+    }
+}
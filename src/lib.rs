@@ -1,20 +1,161 @@
 mod ast;
 #[cfg(not(target_family = "wasm"))]
+pub mod batch;
+#[cfg(not(target_family = "wasm"))]
 pub mod cli;
+pub mod conditional;
+#[cfg(not(target_family = "wasm"))]
+pub mod config;
 pub mod display;
 #[cfg(not(target_family = "wasm"))]
 pub mod dump;
 pub mod error;
+mod fold;
 mod graph;
+mod layout;
+pub mod list;
+#[cfg(not(target_family = "wasm"))]
+pub mod lsp;
+pub mod metrics;
 mod parser;
+#[cfg(not(target_family = "wasm"))]
+pub mod preprocess;
+mod structogram;
 #[cfg(target_family = "wasm")]
 mod wasm;
+#[cfg(not(target_family = "wasm"))]
+pub mod watch;
 
 #[cfg(target_family = "wasm")]
 pub use wasm::*;
 
-use display::{GraphDisplay, GraphDisplayBackend};
-use error::Result;
+use std::{cmp::Reverse, collections::VecDeque, ops::Range};
+
+use conditional::Macros;
+use display::{GraphDisplay, GraphDisplayBackend, RenderOptions};
+use error::{Result, UnreachableCode};
+use graph::GraphNodeType;
+use miette::NamedSource;
+use serde::Deserialize;
+
+/// How lambda expressions in the selected function are drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(target_family = "wasm"), derive(clap::ValueEnum))]
+pub enum LambdaMode {
+    /// Keep lambdas as part of the statement defining them
+    #[default]
+    Inline,
+    /// Give every lambda a chart of its own, named after the variable it is assigned to
+    Separate,
+    /// Draw lambdas inside the function chart, linked from the statement defining them
+    Nested,
+}
+
+/// How code which can never run is drawn, see [`unreachable_code`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(target_family = "wasm"), derive(clap::ValueEnum))]
+pub enum UnreachableMode {
+    /// Draw it like any other code, apart from the rest of the flow
+    #[default]
+    Show,
+    /// Draw it dashed and grayed out
+    Highlight,
+    /// Leave it out, together with the branches of literal conditions which are never taken
+    Omit,
+}
+
+/// How switch statements are drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(target_family = "wasm"), derive(clap::ValueEnum))]
+pub enum SwitchMode {
+    /// A chain of `cond == case` decisions, one per case
+    #[default]
+    Ladder,
+    /// A single decision with an edge to every case, labeled with the case values
+    MultiWay,
+}
+
+/// Options of the passes turning a function into the graph being drawn
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphOptions {
+    /// Only [`generate_with_options`] draws lambdas apart, elsewhere they stay inline
+    pub lambdas: LambdaMode,
+    pub unreachable: UnreachableMode,
+    pub switches: SwitchMode,
+    /// Merge statements running one after another into basic blocks
+    pub basic_blocks: bool,
+    /// Longest basic block, in lines of code
+    pub max_block_lines: Option<usize>,
+    /// Fold loops, ifs and switches nested deeper than this into a single statement
+    pub max_depth: Option<usize>,
+    /// Give the code folded by `max_depth` charts of their own, only [`generate_with_options`]
+    /// draws them
+    pub fold_charts: bool,
+    /// Keep only the branch taken of every `if constexpr` with a literal condition
+    pub prune_constexpr: bool,
+    /// Mark where coroutines go on after `co_await` and `co_yield`
+    pub resume_edges: bool,
+    /// Lead every return to an end node of its own
+    pub separate_returns: bool,
+}
+
+/// Run the passes on `ast` which come before the graph is built, returns the folded regions
+fn rewrite_ast(
+    ast: &std::rc::Rc<std::cell::RefCell<ast::Ast>>,
+    source: &str,
+    options: &GraphOptions,
+) -> Vec<fold::Region> {
+    if options.prune_constexpr {
+        parser::prune_constexpr(ast.clone());
+    }
+    match options.max_depth {
+        Some(max_depth) => fold::fold(ast, max_depth, source),
+        None => Vec::new(),
+    }
+}
+
+fn graph_of(
+    ast: std::rc::Rc<std::cell::RefCell<ast::Ast>>,
+    source: &str,
+    file_name: &str,
+    options: &GraphOptions,
+) -> Result<graph::Graph> {
+    rewrite_ast(&ast, source, options);
+    graph_of_folded(ast, source, file_name, options)
+}
+
+/// Like [`graph_of`], for code which [`rewrite_ast`] went through already
+fn graph_of_folded(
+    ast: std::rc::Rc<std::cell::RefCell<ast::Ast>>,
+    source: &str,
+    file_name: &str,
+    options: &GraphOptions,
+) -> Result<graph::Graph> {
+    let mut graph = graph::from_ast_with_switches(ast, source, file_name, options.switches)?;
+    match options.unreachable {
+        UnreachableMode::Show => {}
+        UnreachableMode::Highlight => {
+            for node in graph::unreachable(&graph) {
+                graph[node].unreachable = true;
+            }
+        }
+        UnreachableMode::Omit => graph::remove_dead_code(&mut graph),
+    }
+    if options.separate_returns {
+        graph::separate_returns(&mut graph);
+    }
+    if options.basic_blocks {
+        graph::collapse_blocks(&mut graph, options.max_block_lines);
+    }
+    if options.resume_edges {
+        graph::mark_resumes(&mut graph);
+    }
+    Ok(graph)
+}
+
 pub fn generate(
     content: &[u8],
     file_name: &str,
@@ -27,3 +168,270 @@ pub fn generate(
     // dbg!(&graph);
     backend.generate_from_graph(&graph)
 }
+
+/// Like [`generate`], with the passes set up by `options`.
+pub fn generate_with_options(
+    content: &[u8],
+    file_name: &str,
+    function_name: Option<String>,
+    backend: GraphDisplayBackend,
+    options: &GraphOptions,
+) -> Result<String> {
+    let source = String::from_utf8(content.to_vec())?;
+    let ast = parser::parse(content, file_name, function_name.clone())?;
+    let mut regions = rewrite_ast(&ast, &source, options);
+    if !options.fold_charts {
+        regions.clear();
+    }
+    let mut graph = graph_of_folded(ast, &source, file_name, options)?;
+    if options.lambdas == LambdaMode::Inline && regions.is_empty() {
+        return backend.generate_from_graph(&graph);
+    }
+    let name = function_name.clone().unwrap_or_else(|| "main".to_owned());
+    let mut graphs = Vec::new();
+    let mut regions = VecDeque::from(regions);
+    while let Some(region) = regions.pop_front() {
+        regions.extend(fold::fold_region(
+            &region,
+            options.max_depth.unwrap_or_default(),
+            &source,
+        ));
+        let region_graph = graph_of_folded(region.ast, &source, file_name, options)?;
+        graphs.push((format!("{name} L{}", region.line), region_graph));
+    }
+    let lambdas = match options.lambdas {
+        LambdaMode::Inline => Vec::new(),
+        _ => parser::parse_lambdas(content, file_name, function_name)?,
+    };
+    for lambda in lambdas {
+        let lambda_graph = graph_of(lambda.ast, &source, file_name, options)?;
+        if options.lambdas == LambdaMode::Nested {
+            graph::nest(&mut graph, &lambda.name, &lambda.range, &lambda_graph);
+        } else {
+            graphs.push((lambda.name, lambda_graph));
+        }
+    }
+    if graphs.is_empty() {
+        return backend.generate_from_graph(&graph);
+    }
+    graphs.insert(0, (name, graph));
+    backend.generate_from_graphs(&graphs)
+}
+
+/// Like [`generate`], but for the innermost function definition spanning the 1-based `line`.
+///
+/// Returns the function name with the chart, or `None` if no definition spans the line.
+pub fn generate_at_line(
+    content: &[u8],
+    file_name: &str,
+    line: usize,
+    backend: GraphDisplayBackend,
+    options: &GraphOptions,
+) -> Result<Option<(String, String)>> {
+    let Some(function) = parser::parse_definitions(content, file_name)?
+        .into_iter()
+        .filter(|x| x.lines.0 <= line && line <= x.lines.1)
+        .min_by_key(|x| x.lines.1 - x.lines.0)
+    else {
+        return Ok(None);
+    };
+    // only the function under the cursor has to be drawn, whatever the others contain
+    let graph = graph_of(
+        function.ast?,
+        &String::from_utf8(content.to_vec())?,
+        file_name,
+        options,
+    )?;
+    Ok(Some((function.name, backend.generate_from_graph(&graph)?)))
+}
+
+/// Blank out preprocessor directives, which the parser cannot make sense of, and the lines of
+/// the `#if` branches which are not compiled under `macros`.
+///
+/// The lines themselves are kept, so positions in the result match the input.
+pub fn blank_directives(content: &str, macros: &Macros) -> String {
+    conditional::blank_directives(content, macros)
+}
+
+fn graphs_of_all(
+    content: &[u8],
+    file_name: &str,
+    options: &GraphOptions,
+) -> Result<Vec<(String, graph::Graph)>> {
+    let source = String::from_utf8(content.to_vec())?;
+    parser::parse_all(content, file_name)?
+        .into_iter()
+        .map(|(name, ast)| Ok((name, graph_of(ast, &source, file_name, options)?)))
+        .collect()
+}
+
+/// Warning pointing at the outermost statements of `graph` which can never run
+fn unreachable_warning(
+    function: String,
+    graph: &graph::Graph,
+    source: &str,
+    file_name: &str,
+) -> Option<UnreachableCode> {
+    let mut ranges: Vec<Range<usize>> = graph::unreachable(graph)
+        .into_iter()
+        .filter(|x| {
+            matches!(
+                graph[*x].node_type,
+                GraphNodeType::Node(_)
+                    | GraphNodeType::Suspend(_)
+                    | GraphNodeType::Return(_)
+                    | GraphNodeType::Jump(_)
+                    | GraphNodeType::Choice(_)
+                    | GraphNodeType::Loop(_)
+            )
+        })
+        .filter_map(|x| graph[x].range.clone())
+        .collect();
+    ranges.sort_by_key(|x| (x.start, Reverse(x.end)));
+    // statements inside an unreachable loop or branch are covered by it
+    let mut outermost: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        if outermost.last().is_none_or(|x| range.end > x.end) {
+            outermost.push(range);
+        }
+    }
+    (!outermost.is_empty()).then(|| UnreachableCode {
+        function,
+        src: NamedSource::new(file_name, source.to_owned()),
+        ranges: outermost.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// Find the statements of a single function which can never run, like those following a
+/// `return` or an endless loop.
+pub fn unreachable_code(
+    content: &[u8],
+    file_name: &str,
+    function_name: Option<String>,
+) -> Result<Option<UnreachableCode>> {
+    let source = String::from_utf8(content.to_vec())?;
+    let name = function_name.clone().unwrap_or_else(|| "main".to_owned());
+    let ast = parser::parse(content, file_name, function_name)?;
+    let graph = graph::from_ast(ast, &source, file_name)?;
+    Ok(unreachable_warning(name, &graph, &source, file_name))
+}
+
+/// Find the statements which can never run in every function definition, in document order.
+pub fn unreachable_code_all(content: &[u8], file_name: &str) -> Result<Vec<UnreachableCode>> {
+    let source = String::from_utf8(content.to_vec())?;
+    parser::parse_all(content, file_name)?
+        .into_iter()
+        .map(|(name, ast)| {
+            let graph = graph::from_ast(ast, &source, file_name)?;
+            Ok(unreachable_warning(name, &graph, &source, file_name))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Compute control flow metrics of a single function.
+pub fn metrics(
+    content: &[u8],
+    file_name: &str,
+    function_name: Option<String>,
+) -> Result<metrics::Metrics> {
+    let ast = parser::parse(content, file_name, function_name)?;
+    let graph = graph::from_ast(
+        ast.clone(),
+        &String::from_utf8(content.to_vec())?,
+        file_name,
+    )?;
+    Ok(metrics::compute(&ast.borrow(), &graph))
+}
+
+/// Compute control flow metrics of every function definition, in document order.
+pub fn metrics_all(content: &[u8], file_name: &str) -> Result<Vec<(String, metrics::Metrics)>> {
+    let source = String::from_utf8(content.to_vec())?;
+    parser::parse_all(content, file_name)?
+        .into_iter()
+        .map(|(name, ast)| {
+            let graph = graph::from_ast(ast.clone(), &source, file_name)?;
+            Ok((name, metrics::compute(&ast.borrow(), &graph)))
+        })
+        .collect()
+}
+
+/// List every function definition, in document order.
+///
+/// A function whose flow graph cannot be built is still listed, with the error in place of its
+/// complexity.
+pub fn list_functions(content: &[u8], file_name: &str) -> Result<Vec<list::FunctionInfo>> {
+    let source = String::from_utf8(content.to_vec())?;
+    Ok(parser::parse_definitions(content, file_name)?
+        .into_iter()
+        .map(|function| {
+            let complexity = function.ast.and_then(|ast| {
+                let graph = graph::from_ast(ast.clone(), &source, file_name)?;
+                Ok(metrics::compute(&ast.borrow(), &graph))
+            });
+            list::FunctionInfo {
+                name: function.name,
+                qualified_name: function.qualified_name,
+                signature: function.signature,
+                start_line: function.lines.0,
+                end_line: function.lines.1,
+                cyclomatic_complexity: complexity.as_ref().ok().map(|x| x.cyclomatic_complexity),
+                error: complexity.err().map(|x| x.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// Draw a Nassi–Shneiderman diagram (structogram) of a single function as SVG.
+pub fn generate_structogram(
+    content: &[u8],
+    file_name: &str,
+    function_name: Option<String>,
+    options: &RenderOptions,
+) -> Result<String> {
+    let name = function_name.clone().unwrap_or_else(|| "main".to_owned());
+    let ast = parser::parse(content, file_name, function_name)?;
+    structogram::render(
+        &[(name, ast)],
+        &String::from_utf8(content.to_vec())?,
+        file_name,
+        options,
+    )
+}
+
+/// Draw structograms of every function definition into a single SVG document.
+pub fn generate_structogram_all(
+    content: &[u8],
+    file_name: &str,
+    options: &RenderOptions,
+) -> Result<String> {
+    structogram::render(
+        &parser::parse_all(content, file_name)?,
+        &String::from_utf8(content.to_vec())?,
+        file_name,
+        options,
+    )
+}
+
+/// Generate one chart per function definition, as (function name, chart) in document order.
+pub fn generate_all(
+    content: &[u8],
+    file_name: &str,
+    backend: GraphDisplayBackend,
+    options: &GraphOptions,
+) -> Result<Vec<(String, String)>> {
+    graphs_of_all(content, file_name, options)?
+        .into_iter()
+        .map(|(name, graph)| Ok((name, backend.generate_from_graph(&graph)?)))
+        .collect()
+}
+
+/// Generate charts of every function definition, combined into a single document.
+pub fn generate_combined(
+    content: &[u8],
+    file_name: &str,
+    backend: GraphDisplayBackend,
+    options: &GraphOptions,
+) -> Result<String> {
+    backend.generate_from_graphs(&graphs_of_all(content, file_name, options)?)
+}
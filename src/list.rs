@@ -0,0 +1,64 @@
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// Bumped whenever the layout of the JSON listing changes in an incompatible way
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionInfo {
+    /// Unique name which selects this function, e.g. `ns::Foo::run` or `run(int)`
+    pub name: String,
+    pub qualified_name: String,
+    /// Declaration text up to the body
+    pub signature: String,
+    /// 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    /// `None` when no flow graph can be built for the function, see `error`
+    pub cyclomatic_complexity: Option<usize>,
+    /// Why the flow graph of the function cannot be built
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn to_text(functions: &[FunctionInfo]) -> String {
+    let width = functions
+        .iter()
+        .map(|x| x.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut res = String::new();
+    for i in functions {
+        let lines = format!("{}-{}", i.start_line, i.end_line);
+        let complexity = i
+            .cyclomatic_complexity
+            .map_or_else(|| "-".to_owned(), |x| x.to_string());
+        res.push_str(
+            format!(
+                "{:<width$}  lines {:<9}  complexity {:<3}  {}",
+                i.name, lines, complexity, i.signature
+            )
+            .as_str(),
+        );
+        if let Some(error) = &i.error {
+            res.push_str(format!("  error: {error}").as_str());
+        }
+        res.push('\n');
+    }
+    res
+}
+
+#[derive(Serialize)]
+struct JsonList<'a> {
+    version: u32,
+    functions: &'a [FunctionInfo],
+}
+
+pub fn to_json(functions: &[FunctionInfo]) -> Result<String> {
+    let list = JsonList {
+        version: SCHEMA_VERSION,
+        functions,
+    };
+    Ok(serde_json::to_string_pretty(&list)? + "\n")
+}
@@ -0,0 +1,298 @@
+//! Language server serving flow charts of the function under the cursor.
+//!
+//! Besides the standard document sync, the server answers `textDocument/codeLens` with the
+//! cyclomatic complexity of every function, and renders charts on the custom [`Render`]
+//! request or on the [`RENDER_COMMAND`] command attached to the lenses.
+
+use std::{collections::HashMap, path::Path};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response, ResponseError};
+use lsp_types::{
+    CodeLens, CodeLensOptions, CodeLensParams, Command, ExecuteCommandOptions,
+    ExecuteCommandParams, Position, Range, ServerCapabilities, TextDocumentIdentifier,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification,
+    },
+    request::{CodeLensRequest, ExecuteCommand, Request as LspRequest},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::conditional::Macros;
+use crate::display::{GraphDisplayBackend, dot::Dot, json::Json, svg::Svg};
+use crate::error::{Error, Result};
+use crate::{GraphOptions, blank_directives, generate_at_line, list_functions};
+
+/// Command attached to the code lenses, its only argument is a [`RenderParams`]
+pub const RENDER_COMMAND: &str = "cxx2flow.render";
+
+/// `cxx2flow/render`, draws the function enclosing a position
+pub enum Render {}
+
+impl LspRequest for Render {
+    type Params = RenderParams;
+    type Result = Option<RenderResult>;
+    const METHOD: &'static str = "cxx2flow/render";
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    #[default]
+    Dot,
+    Svg,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    #[serde(default)]
+    pub format: RenderFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderResult {
+    /// Name selecting the rendered function, as listed by `--list`
+    pub function: String,
+    pub format: RenderFormat,
+    pub content: String,
+}
+
+type Reply<T> = std::result::Result<T, ResponseError>;
+
+fn failure(code: ErrorCode, message: impl Into<String>) -> ResponseError {
+    ResponseError {
+        code: code as i32,
+        message: message.into(),
+        data: None,
+    }
+}
+
+fn params<R: LspRequest>(request: Request) -> Reply<R::Params> {
+    request
+        .extract(R::METHOD)
+        .map(|(_, params)| params)
+        .map_err(|error| failure(ErrorCode::InvalidParams, error.to_string()))
+}
+
+fn to_json(value: impl Serialize) -> Reply<serde_json::Value> {
+    serde_json::to_value(value)
+        .map_err(|error| failure(ErrorCode::InternalError, error.to_string()))
+}
+
+/// Macros predefined for the language of the document at `uri`
+fn predefined_macros(uri: &Uri) -> Macros {
+    Macros::predefined(None, Some(Path::new(uri.path().as_str())))
+}
+
+struct Server {
+    /// open documents, with preprocessor directives blanked out
+    documents: HashMap<Uri, String>,
+}
+
+impl Server {
+    fn document(&self, uri: &Uri) -> Reply<&str> {
+        self.documents.get(uri).map(String::as_str).ok_or_else(|| {
+            failure(
+                ErrorCode::InvalidParams,
+                format!("document {} is not open", uri.as_str()),
+            )
+        })
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            CodeLensRequest::METHOD => {
+                params::<CodeLensRequest>(request).and_then(|x| to_json(self.code_lens(x)?))
+            }
+            Render::METHOD => params::<Render>(request).and_then(|x| to_json(self.render(x)?)),
+            ExecuteCommand::METHOD => {
+                params::<ExecuteCommand>(request).and_then(|x| to_json(self.execute_command(x)?))
+            }
+            method => Err(failure(
+                ErrorCode::MethodNotFound,
+                format!("unknown request {method}"),
+            )),
+        };
+        match result {
+            Ok(result) => Response {
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => Response {
+                id,
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = notification
+                    .extract::<<DidOpenTextDocument as LspNotification>::Params>(
+                        DidOpenTextDocument::METHOD,
+                    )
+                {
+                    let text = blank_directives(
+                        &params.text_document.text,
+                        &predefined_macros(&params.text_document.uri),
+                    );
+                    self.documents.insert(params.text_document.uri, text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                // documents are synced in full, so the last change holds the whole text
+                if let Ok(mut params) = notification
+                    .extract::<<DidChangeTextDocument as LspNotification>::Params>(
+                    DidChangeTextDocument::METHOD,
+                ) && let Some(change) = params.content_changes.pop()
+                {
+                    let text = blank_directives(
+                        &change.text,
+                        &predefined_macros(&params.text_document.uri),
+                    );
+                    self.documents.insert(params.text_document.uri, text);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = notification
+                    .extract::<<DidCloseTextDocument as LspNotification>::Params>(
+                        DidCloseTextDocument::METHOD,
+                    )
+                {
+                    self.documents.remove(&params.text_document.uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn code_lens(&self, params: CodeLensParams) -> Reply<Option<Vec<CodeLens>>> {
+        let uri = &params.text_document.uri;
+        let content = self.document(uri)?;
+        // the document is often broken while typing, show no lenses until it parses again
+        let Ok(functions) = list_functions(content.as_bytes(), uri.as_str()) else {
+            return Ok(None);
+        };
+        let lenses = functions
+            .into_iter()
+            .map(|function| {
+                let position = Position::new(function.start_line as u32 - 1, 0);
+                // functions without a flow graph show why instead
+                let title = match function.cyclomatic_complexity {
+                    Some(complexity) => format!("cyclomatic complexity {complexity}"),
+                    None => function.error.unwrap_or_default(),
+                };
+                let argument = RenderParams {
+                    text_document: params.text_document.clone(),
+                    position,
+                    format: RenderFormat::Dot,
+                };
+                Ok(CodeLens {
+                    range: Range::new(position, position),
+                    command: Some(Command {
+                        title,
+                        command: RENDER_COMMAND.to_owned(),
+                        arguments: Some(vec![to_json(argument)?]),
+                    }),
+                    data: None,
+                })
+            })
+            .collect::<Reply<Vec<_>>>()?;
+        Ok(Some(lenses))
+    }
+
+    fn render(&self, params: RenderParams) -> Reply<Option<RenderResult>> {
+        let uri = &params.text_document.uri;
+        let content = self.document(uri)?;
+        let backend: GraphDisplayBackend = match params.format {
+            RenderFormat::Dot => Dot::new(false).with_link(uri.as_str()).into(),
+            RenderFormat::Svg => Svg::new().into(),
+            RenderFormat::Json => Json::new().into(),
+        };
+        let line = params.position.line as usize + 1;
+        let chart = generate_at_line(
+            content.as_bytes(),
+            uri.as_str(),
+            line,
+            backend,
+            &GraphOptions::default(),
+        )
+        .map_err(|error| failure(ErrorCode::RequestFailed, error.to_string()))?;
+        Ok(chart.map(|(function, content)| RenderResult {
+            function,
+            format: params.format,
+            content,
+        }))
+    }
+
+    fn execute_command(&self, params: ExecuteCommandParams) -> Reply<Option<RenderResult>> {
+        if params.command != RENDER_COMMAND {
+            return Err(failure(
+                ErrorCode::InvalidParams,
+                format!("unknown command {}", params.command),
+            ));
+        }
+        let argument = params.arguments.into_iter().next().ok_or_else(|| {
+            failure(
+                ErrorCode::InvalidParams,
+                format!("{RENDER_COMMAND} takes the render parameters"),
+            )
+        })?;
+        let argument = serde_json::from_value(argument)
+            .map_err(|error| failure(ErrorCode::InvalidParams, error.to_string()))?;
+        self.render(argument)
+    }
+}
+
+/// Serve `connection` until the client shuts the server down.
+pub fn serve(connection: &Connection) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![RENDER_COMMAND.to_owned()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut server = Server {
+        documents: HashMap::new(),
+    };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection
+                    .sender
+                    .send(server.handle_request(request).into())
+                    .map_err(|_| Error::LspDisconnected)?;
+            }
+            Message::Notification(notification) => server.handle_notification(notification),
+            Message::Response(_) => {}
+        }
+    }
+    Err(Error::LspDisconnected)
+}
+
+/// Run the language server on stdin and stdout.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
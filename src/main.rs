@@ -1,26 +1,123 @@
 use cxx2flow_lib::{
-    cli::Args,
-    display::{d2::D2, dot::Dot, tikz::Tikz},
+    batch::{self, Batch, CompileCommand, find_command, load_compile_commands},
+    cli::{Args, Command},
+    conditional::Macros,
+    config::Settings,
+    display::{
+        GraphDisplay, GraphDisplayBackend, RenderOptions,
+        d2::D2,
+        dot::Dot,
+        json::Json,
+        mermaid::Mermaid,
+        svg::Svg,
+        theme::{BUILTIN_THEMES, Theme},
+        tikz::Tikz,
+    },
     dump,
     error::Error,
+    lsp,
+    preprocess::{PreprocessorOptions, preprocess},
+    watch,
 };
+use notify::{RecursiveMode, Watcher};
 use std::{
-    io::{Read, Write},
-    process::{self, Stdio},
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc,
 };
 use tree_sitter::Parser;
 
-use itertools::Itertools;
-
-use cxx2flow_lib::generate;
+use cxx2flow_lib::{
+    GraphOptions, blank_directives, generate_all, generate_combined, generate_structogram,
+    generate_structogram_all, generate_with_options, list_functions, metrics, metrics_all,
+    unreachable_code, unreachable_code_all,
+};
 use miette::IntoDiagnostic;
 
 fn main() -> miette::Result<()> {
     miette::set_panic_hook();
-    let args = <Args as clap::Parser>::parse();
+    let matches = <Args as clap::CommandFactory>::command().get_matches();
+    let args = <Args as clap::FromArgMatches>::from_arg_matches(&matches)
+        .unwrap_or_else(|error| error.exit());
+    if let Some(Command::Lsp) = args.command {
+        return Ok(lsp::run()?);
+    }
+    let settings = Settings::new(args, matches)?;
+    let input = settings.args.input.as_deref().map(Path::new);
+    if settings.args(input)?.watch {
+        return watch(&settings);
+    }
+    run(&settings)
+}
+
+/// Run once, then again whenever the input changes.
+/// Errors are reported instead of ending the process.
+fn watch(settings: &Settings) -> miette::Result<()> {
+    let input = Path::new(settings.args.input.as_deref().unwrap_or_default());
+    let is_batch = input.is_dir() || batch::is_compile_commands(input);
+    // editors often replace files on save, so watch the directories containing them
+    let dir = if input.is_dir() {
+        input.to_path_buf()
+    } else if is_batch {
+        Batch::new(input)?.root
+    } else {
+        input.parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir
+    };
+    let target = if is_batch {
+        None
+    } else {
+        let file_name = input
+            .file_name()
+            .ok_or_else(|| miette::miette!("cannot watch {}", input.display()))?;
+        Some(dir.canonicalize().into_diagnostic()?.join(file_name))
+    };
+    let changed = |path: &Path| match &target {
+        Some(target) => path == target,
+        None => batch::is_source(path) || batch::is_compile_commands(path),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).into_diagnostic()?;
+    let mode = if is_batch {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(&dir, mode).into_diagnostic()?;
+    watch::run_on_changes(&receiver, changed, || run(settings));
+    Ok(())
+}
+
+/// Write `res` to `output`, or print it if there is none.
+/// Files which already hold `res` are left untouched.
+fn write_output(output: Option<impl AsRef<Path>>, res: &str) -> miette::Result<()> {
+    match output {
+        Some(output) => {
+            watch::write_if_changed(output, res).into_diagnostic()?;
+            Ok(())
+        }
+        None => {
+            print!("{}", res);
+            Ok(())
+        }
+    }
+}
+
+/// Read `input`, or stdin if there is none, through the preprocessor if asked.
+/// The flags of `command` come before the ones on the command line.
+fn read_input(
+    args: &Args,
+    input: Option<&Path>,
+    command: Option<&CompileCommand>,
+) -> miette::Result<Vec<u8>> {
     let mut content: Vec<u8> = Vec::new();
-    match args.input {
-        Some(ref file_name) => {
+    match input {
+        Some(file_name) => {
             content = std::fs::read(file_name).into_diagnostic()?;
         }
         None => {
@@ -29,28 +126,231 @@ fn main() -> miette::Result<()> {
                 .into_diagnostic()?;
         }
     };
+    let mut options = command
+        .map(|x| PreprocessorOptions::from_arguments(&x.arguments(), &x.directory))
+        .unwrap_or_default();
+    options.extend(PreprocessorOptions {
+        include_dirs: args.include_dirs.iter().map(PathBuf::from).collect(),
+        defines: args.defines.clone(),
+        undefines: args.undefines.clone(),
+        std: args.std.clone(),
+    });
     let content = if args.cpp {
-        let mut cpp = process::Command::new("cpp")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .into_diagnostic()?;
-        if let Some(mut child_stdin) = cpp.stdin.take() {
-            child_stdin.write_all(&content).into_diagnostic()?;
+        preprocess(&options, input, &content)?
+    } else {
+        String::from_utf8(content).into_diagnostic()?
+    };
+    // without cpp, #if blocks are decided against the macros of the flags
+    let macros = Macros::predefined(options.std.as_deref(), input)
+        .with_flags(&options.defines, &options.undefines);
+    Ok(blank_directives(&content, &macros).into_bytes())
+}
+
+/// A built-in theme, or else the theme in the file at `name`
+fn load_theme(name: &str) -> miette::Result<Theme> {
+    if let Some(theme) = Theme::builtin(name) {
+        return Ok(theme?);
+    }
+    if !Path::new(name).is_file() {
+        return Err(Error::ThemeNotFound {
+            name: name.to_owned(),
+            builtins: BUILTIN_THEMES
+                .iter()
+                .map(|(x, _)| *x)
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+        .into());
+    }
+    let content = std::fs::read_to_string(name).into_diagnostic()?;
+    Ok(Theme::from_toml(&content, name)?)
+}
+
+fn render_options(args: &Args) -> miette::Result<RenderOptions> {
+    let mut options = RenderOptions::new(args.locale);
+    if let Some((yes, no)) = &args.branch_labels {
+        options.yes = yes.clone();
+        options.no = no.clone();
+    }
+    if let Some((begin, end)) = &args.terminal_labels {
+        options.begin = begin.clone();
+        options.end = end.clone();
+    }
+    options.question_mark = !args.no_question_mark;
+    if let Some(theme) = &args.theme {
+        options.theme = load_theme(theme)?;
+    }
+    Ok(options)
+}
+
+fn backend(args: &Args, file_name: &str) -> miette::Result<GraphDisplayBackend> {
+    let options = render_options(args)?;
+    Ok(if args.tikz {
+        Tikz::new().with_options(options).into()
+    } else if args.d2 {
+        let d2 = D2::new().with_options(options);
+        if args.link {
+            d2.with_link(file_name)
+        } else {
+            d2
+        }
+        .into()
+    } else if args.mermaid {
+        Mermaid::new().with_options(options).into()
+    } else if args.json {
+        Json::new().into()
+    } else if args.svg {
+        Svg::new().with_options(options).into()
+    } else {
+        let dot = Dot::new(args.curly).with_options(options);
+        if args.link {
+            dot.with_link(file_name)
+        } else {
+            dot
+        }
+        .into()
+    })
+}
+
+fn graph_options(args: &Args) -> GraphOptions {
+    GraphOptions {
+        lambdas: args.lambdas,
+        unreachable: args.unreachable,
+        switches: args.switches,
+        basic_blocks: args.basic_blocks,
+        max_block_lines: args.max_block_lines,
+        max_depth: args.max_depth,
+        fold_charts: args.fold_charts,
+        prune_constexpr: args.prune_constexpr,
+        resume_edges: args.resume_edges,
+        separate_returns: args.separate_returns,
+    }
+}
+
+/// Print a warning for every charted function with code which can never run.
+/// `function` is the selected function, or `None` for all of them.
+fn warn_unreachable(
+    content: &[u8],
+    file_name: &str,
+    function: Option<&str>,
+    filter: Option<&glob::Pattern>,
+) -> miette::Result<()> {
+    let warnings = match function {
+        Some(function) => unreachable_code(content, file_name, Some(function.to_owned()))?
+            .into_iter()
+            .collect(),
+        None => unreachable_code_all(content, file_name)?,
+    };
+    for warning in warnings {
+        if filter.is_none_or(|x| x.matches(&warning.function)) {
+            eprintln!("{:?}", miette::Report::new(warning));
+        }
+    }
+    Ok(())
+}
+
+/// Write a chart per function into `dir`, keeping only those matching `--filter`
+fn write_all(args: &Args, dir: &Path, content: &[u8], file_name: &str) -> miette::Result<()> {
+    let filter = args
+        .filter
+        .as_deref()
+        .map(glob::Pattern::new)
+        .transpose()
+        .into_diagnostic()?;
+    let backend = backend(args, file_name)?;
+    let extension = backend.file_extension();
+    let charts: Vec<_> = generate_all(content, file_name, backend, &graph_options(args))?
+        .into_iter()
+        .filter(|(name, _)| filter.as_ref().is_none_or(|x| x.matches(name)))
+        .collect();
+    warn_unreachable(content, file_name, None, filter.as_ref())?;
+    if charts.is_empty() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir).into_diagnostic()?;
+    let mut used: HashMap<String, usize> = HashMap::new();
+    for (name, res) in charts {
+        let mut stem: String = name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        // overloads and methods may share a name
+        let count = used.entry(stem.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            stem = format!("{stem}_{count}");
+        }
+        write_output(Some(dir.join(format!("{stem}.{extension}"))), &res)?;
+    }
+    Ok(())
+}
+
+/// Convert every file of a directory or compilation database into a mirrored output tree.
+/// Files which fail are reported at the end instead of stopping the run.
+fn run_batch(settings: &Settings, input: &Path) -> miette::Result<()> {
+    let args = &settings.args(None)?;
+    let Some(output_dir) = &args.output_dir else {
+        return Err(miette::miette!(
+            help = "e.g. cxx2flow src --all --output-dir charts",
+            "converting {} needs --output-dir",
+            input.display()
+        ));
+    };
+    if args.list || args.metrics || args.dump_ast {
+        return Err(miette::miette!(
+            "--list, --metrics and --dump-ast take a single file"
+        ));
+    }
+    let batch = Batch::new(input)?;
+    let mut errors = Vec::new();
+    for file in &batch.files {
+        let file_name = file.to_string_lossy();
+        let dir = batch.output_dir(Path::new(output_dir), file);
+        if let Err(error) = settings
+            .args(Some(file))
+            .map_err(miette::Report::from)
+            .and_then(|args| {
+                let content = read_input(&args, Some(file), batch.command(file))?;
+                write_all(&args, &dir, &content, &file_name)
+            })
+        {
+            errors.push(error.wrap_err(format!("failed to convert {file_name}")));
         }
-        cpp.wait_with_output().into_diagnostic()?.stdout
+    }
+    for error in &errors {
+        eprintln!("{error:?}");
+    }
+    if errors.is_empty() {
+        Ok(())
     } else {
-        content
+        Err(miette::miette!(
+            "{} of {} files failed",
+            errors.len(),
+            batch.files.len()
+        ))
+    }
+}
+
+fn run(settings: &Settings) -> miette::Result<()> {
+    let input = settings.args.input.as_deref().map(Path::new);
+    if let Some(input) = input
+        && (input.is_dir() || batch::is_compile_commands(input))
+    {
+        return run_batch(settings, input);
+    }
+    let args = &settings.args(input)?;
+    let commands = match &args.compile_commands {
+        Some(path) => load_compile_commands(Path::new(path))?,
+        None => Vec::new(),
     };
-    let content = Itertools::intersperse(
-        String::from_utf8(content)
-            .unwrap()
-            .lines()
-            .filter(|x| !x.starts_with('#')),
-        "\n",
-    )
-    .collect::<String>()
-    .into_bytes();
+    let command =
+        match (&args.compile_commands, input) {
+            (Some(path), Some(input)) => Some(find_command(&commands, input).ok_or_else(|| {
+                miette::miette!("{} is not compiled by {}", input.display(), path)
+            })?),
+            _ => None,
+        };
+    let content = read_input(args, input, command)?;
     if args.dump_ast {
         let mut parser = Parser::new();
         parser
@@ -62,23 +362,61 @@ fn main() -> miette::Result<()> {
         dump::dump_node(&tree.root_node(), &content);
         return Ok(());
     }
-    let backend = if args.tikz {
-        Tikz::new().into()
-    } else if args.d2 {
-        D2::new().into()
+    let file_name = args.input.clone().unwrap_or_else(|| "stdin".to_owned());
+    if args.list {
+        let functions = list_functions(&content, &file_name)?;
+        let res = if args.json {
+            cxx2flow_lib::list::to_json(&functions)?
+        } else {
+            cxx2flow_lib::list::to_text(&functions)
+        };
+        return write_output(args.output.as_ref(), &res);
+    }
+    if args.metrics {
+        let report = if args.all {
+            metrics_all(&content, &file_name)?
+        } else {
+            vec![(
+                args.function.clone(),
+                metrics(&content, &file_name, Some(args.function.clone()))?,
+            )]
+        };
+        let res = if args.json {
+            cxx2flow_lib::metrics::to_json(&report)?
+        } else {
+            cxx2flow_lib::metrics::to_text(&report)
+        };
+        return write_output(args.output.as_ref(), &res);
+    }
+    if args.structogram {
+        let res = if args.all {
+            generate_structogram_all(&content, &file_name, &render_options(args)?)?
+        } else {
+            generate_structogram(
+                &content,
+                &file_name,
+                Some(args.function.clone()),
+                &render_options(args)?,
+            )?
+        };
+        return write_output(args.output.as_ref(), &res);
+    }
+    if let Some(dir) = &args.output_dir {
+        return write_all(args, Path::new(dir), &content, &file_name);
+    }
+    let backend = backend(args, &file_name)?;
+    let res = if args.all {
+        generate_combined(&content, &file_name, backend, &graph_options(args))?
     } else {
-        Dot::new(args.curly).into()
+        generate_with_options(
+            &content,
+            &file_name,
+            Some(args.function.clone()),
+            backend,
+            &graph_options(args),
+        )?
     };
-    let res = generate(
-        &content,
-        &args.input.unwrap_or_else(|| "stdin".to_owned()),
-        Some(args.function),
-        backend,
-    )?;
-    if let Some(output) = args.output {
-        std::fs::write(output, res).into_diagnostic()?;
-    } else {
-        print!("{}", res);
-    }
-    Ok(())
+    let function = (!args.all).then_some(args.function.as_str());
+    warn_unreachable(&content, &file_name, function, None)?;
+    write_output(args.output.as_ref(), &res)
 }
@@ -0,0 +1,138 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use serde::Serialize;
+
+use crate::{
+    ast::{Ast, AstNode},
+    error::Result,
+    graph::{Graph, GraphNodeType, unreachable},
+};
+
+/// Bumped whenever the layout of the JSON report changes in an incompatible way
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Metrics {
+    /// McCabe complexity, E - N + 2 over the part of the flow graph reached from the begin
+    pub cyclomatic_complexity: usize,
+    /// Number of choice nodes which can be reached
+    pub decisions: usize,
+    /// Deepest nesting of for/while/do-while loops
+    pub max_loop_depth: usize,
+    /// Number of edges reaching the end node from code which can run
+    pub exit_paths: usize,
+    pub gotos: usize,
+}
+
+fn max_loop_depth(ast: &Ast) -> usize {
+    let depth = |x: &Rc<RefCell<Ast>>| max_loop_depth(&x.borrow());
+    match &ast.node {
+        AstNode::Compound(v) => v.iter().map(depth).max().unwrap_or(0),
+        AstNode::If {
+            body, otherwise, ..
+        } => depth(body).max(otherwise.as_ref().map(depth).unwrap_or(0)),
+        AstNode::While { body, .. } | AstNode::DoWhile { body, .. } | AstNode::For { body, .. } => {
+            depth(body) + 1
+        }
+        AstNode::Switch { body, .. } => depth(body),
+        AstNode::Try { body, handlers } => handlers
+            .iter()
+            .map(|(_, x)| depth(x))
+            .fold(depth(body), usize::max),
+        _ => 0,
+    }
+}
+
+fn count_gotos(ast: &Ast) -> usize {
+    let count = |x: &Rc<RefCell<Ast>>| count_gotos(&x.borrow());
+    match &ast.node {
+        AstNode::Goto(_) => 1,
+        AstNode::Compound(v) => v.iter().map(count).sum(),
+        AstNode::If {
+            body, otherwise, ..
+        } => count(body) + otherwise.as_ref().map(count).unwrap_or(0),
+        AstNode::While { body, .. }
+        | AstNode::DoWhile { body, .. }
+        | AstNode::For { body, .. }
+        | AstNode::Switch { body, .. } => count(body),
+        AstNode::Try { body, handlers } => {
+            count(body) + handlers.iter().map(|(_, x)| count(x)).sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+pub fn compute(ast: &Ast, graph: &Graph) -> Metrics {
+    // code which never runs adds no paths
+    let dead: HashSet<_> = unreachable(graph).into_iter().collect();
+    let nodes = graph.node_indices().filter(|x| !dead.contains(x)).count();
+    let edges = graph
+        .edge_references()
+        .filter(|x| !dead.contains(&x.source()) && !dead.contains(&x.target()))
+        .collect::<Vec<_>>();
+    let decisions = graph
+        .node_indices()
+        .filter(|x| {
+            !dead.contains(x)
+                && matches!(
+                    graph[*x].node_type,
+                    GraphNodeType::Choice(_) | GraphNodeType::Loop(_)
+                )
+        })
+        .count();
+    let exit_paths = edges
+        .iter()
+        .filter(|x| graph[x.target()].node_type == GraphNodeType::End)
+        .count();
+    Metrics {
+        cyclomatic_complexity: (edges.len() + 2).saturating_sub(nodes),
+        decisions,
+        max_loop_depth: max_loop_depth(ast),
+        exit_paths,
+        gotos: count_gotos(ast),
+    }
+}
+
+pub fn to_text(metrics: &[(String, Metrics)]) -> String {
+    let mut res = String::new();
+    for (name, m) in metrics {
+        res.push_str(
+            format!(
+                "{}\n  cyclomatic complexity: {}\n  decisions: {}\n  max loop depth: {}\n  exit paths: {}\n  gotos: {}\n",
+                name,
+                m.cyclomatic_complexity,
+                m.decisions,
+                m.max_loop_depth,
+                m.exit_paths,
+                m.gotos
+            )
+            .as_str(),
+        );
+    }
+    res
+}
+
+#[derive(Serialize)]
+struct JsonFunction<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    metrics: &'a Metrics,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    version: u32,
+    functions: Vec<JsonFunction<'a>>,
+}
+
+pub fn to_json(metrics: &[(String, Metrics)]) -> Result<String> {
+    let report = JsonReport {
+        version: SCHEMA_VERSION,
+        functions: metrics
+            .iter()
+            .map(|(name, metrics)| JsonFunction { name, metrics })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&report)? + "\n")
+}
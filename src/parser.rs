@@ -1,11 +1,11 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{borrow::Cow, cell::RefCell, ops::Range, rc::Rc};
 
-use crate::ast::{Ast, AstNode};
+use crate::ast::{Ast, AstNode, literal_condition};
 #[cfg(not(target_family = "wasm"))]
 #[allow(unused_imports)]
 use crate::dump::dump_node;
 use crate::error::{Error, Result};
-use tree_sitter::{Node, Parser, TreeCursor};
+use tree_sitter::{Node, Parser, Tree, TreeCursor};
 
 fn filter_ast<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
     if node.kind() == kind {
@@ -25,56 +25,598 @@ fn filter_ast<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
     None
 }
 
-pub fn parse(
-    content: &[u8],
-    _file_name: &str,
-    function_name: Option<String>,
-) -> Result<Rc<RefCell<Ast>>> {
-    let mut parser = Parser::new();
-    parser.set_language(&tree_sitter_cpp::LANGUAGE.into())?;
-    let tree = parser
-        .parse(content, None)
-        .ok_or(Error::TreesitterParseFailed)?;
-    let mut cursor = tree.walk();
-    cursor.goto_first_child();
+/// collect every function definition in document order, without descending into function bodies
+fn collect_functions<'a>(node: Node<'a>, functions: &mut Vec<Node<'a>>) {
+    if node.kind() == "function_definition" {
+        functions.push(node);
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_functions(child, functions);
+    }
+}
+
+/// A function definition, along with the names it can be selected by
+struct FunctionDef<'a> {
+    node: Node<'a>,
+    /// Qualified name, e.g. `ns::Foo::run`
+    name: String,
+    /// Normalized parameter types, e.g. `const char*`
+    params: Vec<String>,
+    /// `const`, `volatile`, `&` or `&&` after the parameter list
+    qualifiers: Vec<String>,
+}
+
+impl FunctionDef<'_> {
+    fn signature(&self) -> String {
+        let qualifiers: String = self.qualifiers.iter().map(|x| format!(" {x}")).collect();
+        format!("{}({}){qualifiers}", self.name, self.params.join(", "))
+    }
+}
+
+/// collapse whitespace, dropping it entirely unless it separates two words
+fn normalize(text: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut res = String::new();
+    let mut pending_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            pending_space = !res.is_empty();
+            continue;
+        }
+        if pending_space && res.ends_with(is_word) && is_word(c) {
+            res.push(' ');
+        }
+        pending_space = false;
+        res.push(c);
+    }
+    res
+}
+
+/// split `text` at every `sep` which is not nested in brackets
+fn split_top_level<'a>(text: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut res = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        match rest.as_bytes()[0] {
+            b'<' | b'(' | b'[' => depth += 1,
+            b'>' | b')' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth == 0 && rest.starts_with(sep) {
+            res.push(&text[start..i]);
+            i += sep.len();
+            start = i;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    res.push(&text[start..]);
+    res
+}
+
+/// names of the namespaces and classes enclosing `node`, outermost first
+fn enclosing_scopes(node: Node, content: &[u8]) -> Result<Vec<String>> {
+    let mut scopes = Vec::new();
+    let mut parent = node.parent();
+    while let Some(node) = parent {
+        if matches!(
+            node.kind(),
+            "namespace_definition" | "class_specifier" | "struct_specifier" | "union_specifier"
+        ) {
+            // anonymous namespaces do not need to be spelled out
+            if let Some(name) = node.child_by_field_name("name") {
+                scopes.push(normalize(name.utf8_text(content)?));
+            }
+        }
+        parent = node.parent();
+    }
+    scopes.reverse();
+    Ok(scopes)
+}
+
+fn parse_params(params: Node, content: &[u8]) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    let mut cursor = params.walk();
+    for param in params.named_children(&mut cursor) {
+        if param.kind() == "comment" {
+            continue;
+        }
+        // the parameter name and default value do not take part in overload resolution
+        let mut end = param.end_byte();
+        let mut name = None;
+        if let Some(declarator) = param.child_by_field_name("declarator") {
+            if param.child_by_field_name("default_value").is_some() {
+                end = declarator.end_byte();
+            }
+            name = filter_ast(declarator, "identifier").map(|x| x.byte_range());
+        }
+        let text = match name {
+            Some(name) => [
+                &content[param.start_byte()..name.start],
+                &content[name.end..end],
+            ]
+            .concat(),
+            None => content[param.start_byte()..end].to_vec(),
+        };
+        res.push(normalize(std::str::from_utf8(&text)?));
+    }
+    if res == ["void"] {
+        res.clear();
+    }
+    Ok(res)
+}
+
+fn get_function_def<'a>(function: Node<'a>, content: &[u8]) -> Result<Option<FunctionDef<'a>>> {
+    let mut declarator = function
+        .child_by_field_name("declarator")
+        .ok_or(Error::DeclaratorNotFound)?;
+    // unwrap pointer and reference return types until the function itself shows up
+    let (name, params) = loop {
+        match declarator.kind() {
+            "function_declarator" => {
+                let name = declarator
+                    .child_by_field_name("declarator")
+                    .ok_or(Error::DeclaratorNotFound)?;
+                break (normalize(name.utf8_text(content)?), declarator);
+            }
+            "operator_cast" => {
+                let ty = declarator
+                    .child_by_field_name("type")
+                    .ok_or(Error::ChildNotFound)?;
+                let name = format!("operator {}", normalize(ty.utf8_text(content)?));
+                break (name, declarator);
+            }
+            _ => match declarator.child_by_field_name("declarator") {
+                Some(inner) => declarator = inner,
+                None => return Ok(None),
+            },
+        }
+    };
+    let (params, qualifiers) = match filter_ast(params, "parameter_list") {
+        Some(params) => {
+            let mut qualifiers = Vec::new();
+            if let Some(declarator) = params.parent() {
+                let mut cursor = declarator.walk();
+                for child in declarator.children(&mut cursor) {
+                    if matches!(child.kind(), "type_qualifier" | "ref_qualifier") {
+                        qualifiers.push(child.utf8_text(content)?.to_owned());
+                    }
+                }
+            }
+            (parse_params(params, content)?, qualifiers)
+        }
+        None => (Vec::new(), Vec::new()),
+    };
+    let mut scopes = enclosing_scopes(function, content)?;
+    scopes.push(name);
+    Ok(Some(FunctionDef {
+        node: function,
+        name: scopes.join("::"),
+        params,
+        qualifiers,
+    }))
+}
+
+fn get_function_defs<'a>(root: Node<'a>, content: &[u8]) -> Result<Vec<FunctionDef<'a>>> {
     let mut functions: Vec<Node> = Vec::new();
-    loop {
-        let node = cursor.node();
-        let node = filter_ast(node, "function_definition");
-        if let Some(node) = node {
-            functions.push(node);
+    collect_functions(root, &mut functions);
+    let mut res = Vec::new();
+    for i in functions {
+        if let Some(def) = get_function_def(i, content)? {
+            res.push(def);
         }
-        if !cursor.goto_next_sibling() {
+    }
+    Ok(res)
+}
+
+/// A function name given by the user, e.g. `run`, `Foo::run`, `::ns::run(int, char*)` or
+/// `get() const`
+struct FunctionQuery {
+    scopes: Vec<String>,
+    /// starts with `::`, so the whole qualified name must match
+    rooted: bool,
+    params: Option<Vec<String>>,
+    /// qualifiers after the parameter list, only given along with it
+    qualifiers: Vec<String>,
+}
+
+/// split `const`, `volatile`, `&` and `&&` off the end of a normalized query with parameters
+fn split_qualifiers(query: &str) -> (&str, Vec<String>) {
+    let mut rest = query;
+    let mut qualifiers = Vec::new();
+    loop {
+        // a qualifier follows the parameter list or another qualifier
+        let found = ["&&", "&", "const", "volatile"].into_iter().find_map(|x| {
+            let before = rest.strip_suffix(x)?.trim_end();
+            (before.ends_with([')', '&'])
+                || before.ends_with("const")
+                || before.ends_with("volatile"))
+            .then_some((x, before))
+        });
+        let Some((qualifier, before)) = found else {
             break;
+        };
+        rest = before;
+        qualifiers.insert(0, qualifier.to_owned());
+    }
+    // `operator&` and the like are names, not qualified parameter lists
+    if rest.ends_with(')') {
+        (rest, qualifiers)
+    } else {
+        (query, Vec::new())
+    }
+}
+
+impl FunctionQuery {
+    fn new(query: &str) -> Self {
+        let query = normalize(query);
+        let (query, qualifiers) = split_qualifiers(&query);
+        let (name, params) = match query.strip_suffix(')') {
+            // `operator()` alone is a name, not a parameter list
+            Some(rest) if !query.ends_with("operator()") => {
+                let mut depth = 0usize;
+                let open = rest.char_indices().rev().find(|(_, c)| match c {
+                    ')' => {
+                        depth += 1;
+                        false
+                    }
+                    '(' if depth > 0 => {
+                        depth -= 1;
+                        false
+                    }
+                    '(' => true,
+                    _ => false,
+                });
+                match open {
+                    Some((open, _)) => {
+                        let params = &rest[open + 1..];
+                        let params = if params.is_empty() || params == "void" {
+                            Vec::new()
+                        } else {
+                            split_top_level(params, ",")
+                                .into_iter()
+                                .map(normalize)
+                                .collect()
+                        };
+                        (&query[..open], Some(params))
+                    }
+                    None => (query, None),
+                }
+            }
+            _ => (query, None),
+        };
+        let (name, rooted) = match name.strip_prefix("::") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        FunctionQuery {
+            scopes: split_top_level(name, "::")
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            rooted,
+            params,
+            qualifiers,
         }
     }
-    let target_function = function_name.unwrap_or_else(|| "main".to_string());
-    for i in functions {
-        cursor.reset(i);
-        let stats = cursor
-            .node()
-            .child_by_field_name("body")
-            .ok_or(Error::ChildNotFound)?;
-        let node = cursor
-            .node()
-            .child_by_field_name("declarator")
-            .ok_or(Error::DeclaratorNotFound)?;
-        let func_name = filter_ast(node, "identifier");
-        if func_name.is_none() {
+
+    fn matches(&self, function: &FunctionDef) -> bool {
+        let scopes = split_top_level(&function.name, "::");
+        let name_matches = if self.rooted {
+            scopes == self.scopes
+        } else {
+            scopes.len() >= self.scopes.len()
+                && scopes[scopes.len() - self.scopes.len()..] == self.scopes
+        };
+        name_matches
+            && self.params.as_ref().is_none_or(|x| *x == function.params)
+            && (self.qualifiers.is_empty() || self.qualifiers == function.qualifiers)
+    }
+
+    /// edit distance between the name in the query and the matching part of `function`'s name
+    fn distance(&self, function: &FunctionDef) -> usize {
+        let scopes = split_top_level(&function.name, "::");
+        let suffix = scopes[scopes.len().saturating_sub(self.scopes.len())..].join("::");
+        edit_distance(&suffix, &self.scopes.join("::"))
+    }
+
+    fn is_exact(&self, function: &FunctionDef) -> bool {
+        split_top_level(&function.name, "::").len() == self.scopes.len()
+    }
+
+    /// whether `function` has the same qualifiers as the parameter list of the query
+    fn is_exactly_qualified(&self, function: &FunctionDef) -> bool {
+        self.params.is_some() && self.qualifiers == function.qualifiers
+    }
+}
+
+fn parse_function(function: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
+    let stats = function
+        .child_by_field_name("body")
+        .ok_or(Error::ChildNotFound)?;
+    let res = parse_stat(stats, content)?;
+    remove_dummy(res.clone());
+    Ok(res)
+}
+
+fn parse_tree(content: &[u8]) -> Result<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(&tree_sitter_cpp::LANGUAGE.into())?;
+    parser
+        .parse(rewrite_consteval(content), None)
+        .ok_or(Error::TreesitterParseFailed)
+}
+
+/// tree-sitter-cpp does not know `if consteval { ... }` yet, so it is parsed as
+/// `if(CONSTEVAL){ ... }`, taking the place of the whitespace around `consteval`, or of its last
+/// letter when it is right before the brace.
+/// Positions are kept, so the text of the nodes is read from the original content.
+fn rewrite_consteval(content: &[u8]) -> Cow<'_, [u8]> {
+    const KEYWORD: &[u8] = b"consteval";
+    let is_identifier = |c: &u8| c.is_ascii_alphanumeric() || *c == b'_';
+    let mut res = Cow::Borrowed(content);
+    let mut start = 0;
+    while let Some(found) = content[start..]
+        .windows(KEYWORD.len())
+        .position(|x| x == KEYWORD)
+    {
+        let keyword = start + found;
+        start = keyword + KEYWORD.len();
+        if content.get(start).is_some_and(is_identifier) {
             continue;
         }
-        let func_name = func_name.unwrap().utf8_text(content)?;
-        if func_name != target_function {
+        // `if`, whitespace, an optional `!` and whitespace, `consteval`, whitespace and `{`
+        let mut before = keyword;
+        let bang = content[..keyword].trim_ascii_end().len();
+        if bang > 0 && content[bang - 1] == b'!' {
+            before = bang - 1;
+        }
+        let open = content[..before].trim_ascii_end().len();
+        let close = start + (content[start..].len() - content[start..].trim_ascii_start().len());
+        if open == before
+            || content.get(close) != Some(&b'{')
+            || !content[..open].ends_with(b"if")
+            || open > 2 && is_identifier(&content[open - 3])
+        {
             continue;
         }
-        let res = parse_stat(stats, content)?;
-        remove_dummy(res.clone());
-        return Ok(res);
+        let res = res.to_mut();
+        res[open] = b'(';
+        if close == start {
+            res[keyword..start].copy_from_slice(b"CONSTEVA)");
+        } else {
+            res[keyword..start].copy_from_slice(b"CONSTEVAL");
+            res[close - 1] = b')';
+        }
+    }
+    res
+}
+
+/// optimal string alignment distance, so that swapped letters (`slove`) count as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// `did you mean ...` hint built from the functions whose names are close to the query
+fn suggest(query: &FunctionQuery, functions: &[FunctionDef]) -> String {
+    let limit = (query.scopes.join("::").chars().count() / 3).max(1);
+    let mut close: Vec<(usize, String)> = functions
+        .iter()
+        .map(|x| (query.distance(x), x))
+        .filter(|(distance, _)| *distance <= limit)
+        .map(|(distance, x)| {
+            // the name is right, so the parameter list must be wrong
+            let name = if distance == 0 {
+                x.signature()
+            } else {
+                x.name.clone()
+            };
+            (distance, name)
+        })
+        .collect();
+    close.sort_by_key(|(distance, _)| *distance);
+    close.dedup_by(|a, b| a.1 == b.1);
+    if close.is_empty() {
+        return String::new();
+    }
+    let names: Vec<String> = close
+        .into_iter()
+        .take(3)
+        .map(|(_, name)| format!("`{name}`"))
+        .collect();
+    format!("\ndid you mean {}?", names.join(", "))
+}
+
+fn select_function<'a, 'b>(
+    functions: &'b [FunctionDef<'a>],
+    function_name: Option<String>,
+) -> Result<&'b FunctionDef<'a>> {
+    let target_function = function_name.unwrap_or_else(|| "main".to_string());
+    let query = FunctionQuery::new(&target_function);
+    let mut candidates: Vec<&FunctionDef> = functions.iter().filter(|x| query.matches(x)).collect();
+    // a name spelled out in full wins over members of the same name
+    if candidates.iter().any(|x| query.is_exact(x)) {
+        candidates.retain(|x| query.is_exact(x));
+    }
+    // `get()` is the unqualified overload when there is one next to `get() const`
+    if candidates.iter().any(|x| query.is_exactly_qualified(x)) {
+        candidates.retain(|x| query.is_exactly_qualified(x));
+    }
+    match candidates.as_slice() {
+        [] => Err(Error::FunctionNotFound {
+            src: target_function.clone(),
+            range: (0..target_function.len()).into(),
+            suggestion: suggest(&query, functions),
+        }),
+        [function] => Ok(function),
+        _ => Err(Error::AmbiguousFunction {
+            src: target_function.clone(),
+            range: (0..target_function.len()).into(),
+            candidates: candidates
+                .iter()
+                .map(|x| {
+                    format!(
+                        "\n  {} at line {}",
+                        x.signature(),
+                        x.node.start_position().row + 1
+                    )
+                })
+                .collect::<String>(),
+        }),
+    }
+}
+
+pub fn parse(
+    content: &[u8],
+    _file_name: &str,
+    function_name: Option<String>,
+) -> Result<Rc<RefCell<Ast>>> {
+    let tree = parse_tree(content)?;
+    let functions = get_function_defs(tree.root_node(), content)?;
+    parse_function(select_function(&functions, function_name)?.node, content)
+}
+
+/// A lambda expression inside a function, with a flow of its own
+pub struct Lambda {
+    /// Variable the lambda is assigned to, or `lambda@<line>`
+    pub name: String,
+    /// Byte range of the whole lambda expression
+    pub range: Range<usize>,
+    pub ast: Rc<RefCell<Ast>>,
+}
+
+/// collect lambda expressions in document order, including the ones nested in other lambdas
+fn collect_lambdas<'a>(node: Node<'a>, lambdas: &mut Vec<Node<'a>>) {
+    if node.kind() == "lambda_expression" {
+        lambdas.push(node);
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_lambdas(child, lambdas);
+    }
+}
+
+fn get_lambda_name(lambda: Node, content: &[u8]) -> Result<String> {
+    let target = lambda.parent().and_then(|parent| match parent.kind() {
+        "init_declarator" => parent.child_by_field_name("declarator"),
+        "assignment_expression" => parent.child_by_field_name("left"),
+        _ => None,
+    });
+    match target {
+        Some(target) => Ok(normalize(target.utf8_text(content)?)),
+        None => Ok(format!("lambda@{}", lambda.start_position().row + 1)),
+    }
+}
+
+/// parse every lambda expression in the body of the target function
+pub fn parse_lambdas(
+    content: &[u8],
+    _file_name: &str,
+    function_name: Option<String>,
+) -> Result<Vec<Lambda>> {
+    let tree = parse_tree(content)?;
+    let functions = get_function_defs(tree.root_node(), content)?;
+    let function = select_function(&functions, function_name)?;
+    let mut lambdas = Vec::new();
+    collect_lambdas(
+        function
+            .node
+            .child_by_field_name("body")
+            .ok_or(Error::ChildNotFound)?,
+        &mut lambdas,
+    );
+    let mut res = Vec::new();
+    for i in lambdas {
+        let body = i.child_by_field_name("body").ok_or(Error::ChildNotFound)?;
+        let ast = parse_stat(body, content)?;
+        remove_dummy(ast.clone());
+        res.push(Lambda {
+            name: get_lambda_name(i, content)?,
+            range: i.byte_range(),
+            ast,
+        });
     }
-    Err(Error::FunctionNotFound {
-        src: target_function.clone(),
-        range: (0..target_function.len()).into(),
-    })
+    Ok(res)
+}
+
+/// A function definition, as listed by `--list`
+pub struct ParsedFunction {
+    /// Unique name which selects this function, e.g. `ns::Foo::run` or `run(int)`
+    pub name: String,
+    pub qualified_name: String,
+    /// Declaration text up to the body, e.g. `static int run(int step) const`
+    pub signature: String,
+    /// 1-based first and last line of the definition
+    pub lines: (usize, usize),
+    /// The body of a function which cannot be parsed does not keep the others from being listed
+    pub ast: Result<Rc<RefCell<Ast>>>,
+}
+
+/// parse every named function definition, in document order
+///
+/// Overloaded functions are named by their parameter list, so that every name is unique.
+pub fn parse_definitions(content: &[u8], _file_name: &str) -> Result<Vec<ParsedFunction>> {
+    let tree = parse_tree(content)?;
+    let functions = get_function_defs(tree.root_node(), content)?;
+    let mut res = Vec::new();
+    for i in &functions {
+        let overloaded = functions.iter().filter(|x| x.name == i.name).count() > 1;
+        let name = if overloaded {
+            i.signature()
+        } else {
+            i.name.clone()
+        };
+        let body = i
+            .node
+            .child_by_field_name("body")
+            .ok_or(Error::ChildNotFound)?;
+        let signature = std::str::from_utf8(&content[i.node.start_byte()..body.start_byte()])?
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        res.push(ParsedFunction {
+            name,
+            qualified_name: i.name.clone(),
+            signature,
+            lines: (
+                i.node.start_position().row + 1,
+                i.node.end_position().row + 1,
+            ),
+            ast: parse_function(i.node, content),
+        });
+    }
+    Ok(res)
+}
+
+/// parse every named function definition, returns (function name, ast) in document order
+pub fn parse_all(content: &[u8], file_name: &str) -> Result<Vec<(String, Rc<RefCell<Ast>>)>> {
+    parse_definitions(content, file_name)?
+        .into_iter()
+        .map(|x| Ok((x.name, x.ast?)))
+        .collect()
 }
 
 fn remove_dummy(ast: Rc<RefCell<Ast>>) {
@@ -93,6 +635,12 @@ fn remove_dummy(ast: Rc<RefCell<Ast>>) {
         | AstNode::Switch { body, .. } => {
             remove_dummy(body.clone());
         }
+        AstNode::Try { body, handlers } => {
+            remove_dummy(body.clone());
+            handlers.iter().for_each(|(_, x)| {
+                remove_dummy(x.clone());
+            });
+        }
         AstNode::Compound(v) => {
             v.retain(|x| !matches!(x.borrow().node, AstNode::Dummy));
             v.iter().for_each(|x| {
@@ -103,6 +651,61 @@ fn remove_dummy(ast: Rc<RefCell<Ast>>) {
     }
 }
 
+/// Value of the condition of an `if constexpr` which is a literal, like `constexpr (true)`
+fn constexpr_literal(cond: &str) -> Option<bool> {
+    literal_condition(cond.strip_prefix("constexpr ")?)
+}
+
+/// Keep only the branch taken of every `if constexpr` with a literal condition
+pub fn prune_constexpr(ast: Rc<RefCell<Ast>>) {
+    let mut current = ast.borrow_mut();
+    let pruned = match &current.node {
+        AstNode::If {
+            cond,
+            body,
+            otherwise,
+            ..
+        } => constexpr_literal(cond).map(|taken| {
+            let branch = if taken {
+                Some(body)
+            } else {
+                otherwise.as_ref()
+            };
+            AstNode::Compound(branch.into_iter().cloned().collect())
+        }),
+        _ => None,
+    };
+    if let Some(pruned) = pruned {
+        current.node = pruned;
+    }
+    match &current.node {
+        AstNode::If {
+            body, otherwise, ..
+        } => {
+            prune_constexpr(body.clone());
+            if let Some(otherwise) = otherwise {
+                prune_constexpr(otherwise.clone());
+            }
+        }
+        AstNode::While { body, .. }
+        | AstNode::DoWhile { body, .. }
+        | AstNode::For { body, .. }
+        | AstNode::Switch { body, .. } => {
+            prune_constexpr(body.clone());
+        }
+        AstNode::Try { body, handlers } => {
+            prune_constexpr(body.clone());
+            handlers.iter().for_each(|(_, x)| {
+                prune_constexpr(x.clone());
+            });
+        }
+        AstNode::Compound(v) => v.iter().for_each(|x| {
+            prune_constexpr(x.clone());
+        }),
+        _ => {}
+    }
+}
+
 fn parse_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     match stat.kind() {
         "compound_statement" => {
@@ -129,6 +732,24 @@ fn parse_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
                 None,
             ))))
         }
+        // a case nested into another statement of the switch body, like in Duff's device
+        "case_statement" => {
+            let (child, label) = get_case_child_and_label(stat.walk(), content)?;
+            let mut stats = Vec::new();
+            if let Some(mut cursor) = child {
+                loop {
+                    stats.push(parse_stat(cursor.node(), content)?);
+                    if !cursor.goto_next_sibling() {
+                        break;
+                    }
+                }
+            }
+            Ok(Rc::new(RefCell::new(Ast::new(
+                AstNode::Compound(stats),
+                stat.byte_range(),
+                Some(vec![label]),
+            ))))
+        }
         "labeled_statement" => {
             let mut label_vec = Vec::new();
             let mut cursor = stat.walk();
@@ -181,7 +802,7 @@ fn parse_single_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
             stat.byte_range(),
             None,
         )))),
-        "return_statement" => {
+        "return_statement" | "co_return_statement" => {
             let str = stat.utf8_text(content)?;
             Ok(Rc::new(RefCell::new(Ast::new(
                 AstNode::Return(String::from(str)),
@@ -189,6 +810,14 @@ fn parse_single_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
                 None,
             ))))
         }
+        "throw_statement" => {
+            let str = stat.utf8_text(content)?;
+            Ok(Rc::new(RefCell::new(Ast::new(
+                AstNode::Throw(String::from(str)),
+                stat.byte_range(),
+                None,
+            ))))
+        }
         "if_statement" => parse_if_stat(stat, content),
         "while_statement" => parse_while_stat(stat, content),
         "do_statement" => parse_do_while_stat(stat, content),
@@ -196,10 +825,24 @@ fn parse_single_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
         "for_range_loop" => parse_range_for_stat(stat, content),
         "switch_statement" => parse_switch_stat(stat, content),
         "goto_statement" => parse_goto_stat(stat, content),
-        "expression_statement" | "declaration" => {
+        "try_statement" => parse_try_stat(stat, content),
+        "co_yield_statement" => {
             let str = stat.utf8_text(content)?;
             Ok(Rc::new(RefCell::new(Ast::new(
-                AstNode::Stat(String::from(str)),
+                AstNode::Suspend(String::from(str)),
+                stat.byte_range(),
+                None,
+            ))))
+        }
+        "expression_statement" | "declaration" => {
+            let str = String::from(stat.utf8_text(content)?);
+            let node = if awaits(stat) {
+                AstNode::Suspend(str)
+            } else {
+                AstNode::Stat(str)
+            };
+            Ok(Rc::new(RefCell::new(Ast::new(
+                node,
                 stat.byte_range(),
                 None,
             ))))
@@ -209,13 +852,89 @@ fn parse_single_stat(stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     }
 }
 
+/// Init-statement, text and range of a condition
+type Condition = (Option<Rc<RefCell<Ast>>>, String, Range<usize>);
+
+/// Split a `condition_clause` into its init-statement, if any, and the condition itself,
+/// parenthesized like the clause
+fn parse_condition_clause(condition: Node, content: &[u8]) -> Result<Condition> {
+    let Some(init) = condition.child_by_field_name("initializer") else {
+        return Ok((
+            None,
+            String::from(condition.utf8_text(content)?),
+            condition.byte_range(),
+        ));
+    };
+    let value = condition
+        .child_by_field_name("value")
+        .ok_or(Error::ChildNotFound)?;
+    let init = Rc::new(RefCell::new(Ast::new(
+        AstNode::Stat(String::from(init.utf8_text(content)?)),
+        init.byte_range(),
+        None,
+    )));
+    Ok((
+        Some(init),
+        format!("({})", value.utf8_text(content)?),
+        value.byte_range(),
+    ))
+}
+
+/// Run the init-statement of an `if`, `switch` or range-based `for` before the statement itself
+fn with_init(
+    init: Option<Rc<RefCell<Ast>>>,
+    stat: Rc<RefCell<Ast>>,
+    range: Range<usize>,
+) -> Rc<RefCell<Ast>> {
+    match init {
+        Some(init) => Rc::new(RefCell::new(Ast::new(
+            AstNode::Compound(vec![init, stat]),
+            range,
+            None,
+        ))),
+        None => stat,
+    }
+}
+
+/// whether `node` has a `co_await`, leaving out the ones of the lambdas it defines
+fn awaits(node: Node) -> bool {
+    match node.kind() {
+        "co_await_expression" => true,
+        "lambda_expression" => false,
+        _ => {
+            let mut cursor = node.walk();
+            node.children(&mut cursor).any(awaits)
+        }
+    }
+}
+
 fn parse_if_stat(if_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     let condition = if_stat
         .child_by_field_name("condition")
         .ok_or(Error::ChildNotFound)?;
     let blk1 = if_stat.child_by_field_name("consequence");
     let blk2 = if_stat.child_by_field_name("alternative");
-    let cond_str = condition.utf8_text(content)?;
+    let (init, cond_str, cond_range) = parse_condition_clause(condition, content)?;
+    // compile-time branches are marked in their condition, e.g. `constexpr (N > 0)`
+    let mut cursor = if_stat.walk();
+    let cond_str = if if_stat
+        .children(&mut cursor)
+        .any(|x| x.kind() == "constexpr")
+    {
+        format!("constexpr {cond_str}")
+    } else {
+        // see `rewrite_consteval`, the parentheses stand in for whitespace or part of the keyword
+        let text: String = condition
+            .utf8_text(content)?
+            .chars()
+            .filter(|x| !x.is_whitespace())
+            .collect();
+        if matches!(text.as_str(), "consteval" | "!consteval") {
+            text
+        } else {
+            cond_str
+        }
+    };
     let body = parse_stat(blk1.ok_or(Error::ChildNotFound)?, content)?;
 
     let otherwise = if let Some(blk2) = blk2 {
@@ -231,14 +950,15 @@ fn parse_if_stat(if_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
 
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::If {
-            cond: String::from(cond_str),
+            cond: cond_str,
+            cond_range,
             body,
             otherwise,
         },
         if_stat.byte_range(),
         None,
     )));
-    Ok(res)
+    Ok(with_init(init, res, if_stat.byte_range()))
 }
 
 fn parse_while_stat(while_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
@@ -252,6 +972,7 @@ fn parse_while_stat(while_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::While {
             cond: String::from(cond_str),
+            cond_range: condition.byte_range(),
             body,
         },
         while_stat.byte_range(),
@@ -261,33 +982,41 @@ fn parse_while_stat(while_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>
 }
 
 /// return first child, or return the case label
+/// The value of a case, or `default`
+fn case_label(case_stat: Node, content: &[u8]) -> Result<String> {
+    let first = case_stat.child(0).ok_or(Error::ChildNotFound)?;
+    let label = if first.kind() == "case" {
+        case_stat
+            .child(1)
+            .ok_or(Error::ChildNotFound)?
+            .utf8_text(content)?
+    } else {
+        first.utf8_text(content)?
+    };
+    Ok(label.into())
+}
+
+/// Values of the cases of a switch in source order, including the ones nested into other
+/// statements of its body like in Duff's device, but not the ones of inner switches
+fn case_labels(node: Node, content: &[u8], cases: &mut Vec<String>) -> Result<()> {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "case_statement" {
+            cases.push(case_label(child, content)?);
+        }
+        if child.kind() != "switch_statement" {
+            case_labels(child, content, cases)?;
+        }
+    }
+    Ok(())
+}
+
 fn get_case_child_and_label<'a>(
     mut case_stat: tree_sitter::TreeCursor<'a>,
     content: &[u8],
 ) -> Result<(Option<TreeCursor<'a>>, String)> {
     // dump_node(&case_stat.node(), None);
-    let label = {
-        let tmp = if case_stat
-            .node()
-            .child(0)
-            .ok_or(Error::ChildNotFound)?
-            .kind()
-            == "case"
-        {
-            case_stat
-                .node()
-                .child(1)
-                .ok_or(Error::ChildNotFound)?
-                .utf8_text(content)?
-        } else {
-            case_stat
-                .node()
-                .child(0)
-                .ok_or(Error::ChildNotFound)?
-                .utf8_text(content)?
-        };
-        tmp.into()
-    };
+    let label = case_label(case_stat.node(), content)?;
     case_stat.goto_first_child();
     if case_stat.node().kind() == "case" {
         // case lit :
@@ -313,10 +1042,11 @@ fn parse_switch_stat(switch_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast
     let body = switch_stat
         .child_by_field_name("body")
         .ok_or(Error::ChildNotFound)?;
-    let cond_str = condition.utf8_text(content)?;
+    let (init, cond_str, cond_range) = parse_condition_clause(condition, content)?;
     let mut stats = Vec::new();
     let mut labels = Vec::new();
     let mut cases = Vec::new();
+    case_labels(body, content, &mut cases)?;
     let mut cursor = body.walk();
     let mut has_case = false;
     if cursor.goto_first_child() {
@@ -331,8 +1061,7 @@ fn parse_switch_stat(switch_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast
     if has_case {
         loop {
             let (child, label) = get_case_child_and_label(cursor.clone(), content)?;
-            labels.push(label.clone());
-            cases.push(label);
+            labels.push(label);
             if let Some(child) = child {
                 let mut cursor = child;
                 let first_idx = stats.len();
@@ -366,14 +1095,15 @@ fn parse_switch_stat(switch_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast
     )));
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::Switch {
-            cond: String::from(cond_str),
+            cond: cond_str,
+            cond_range,
             cases,
             body: inner,
         },
         switch_stat.byte_range(),
         None,
     )));
-    Ok(res)
+    Ok(with_init(init, res, switch_stat.byte_range()))
 }
 
 fn parse_goto_stat(goto_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
@@ -388,6 +1118,39 @@ fn parse_goto_stat(goto_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>>
     ))))
 }
 
+fn parse_try_stat(try_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
+    let body = try_stat
+        .child_by_field_name("body")
+        .ok_or(Error::ChildNotFound)?;
+    let body = parse_stat(body, content)?;
+    let mut handlers = Vec::new();
+    let mut cursor = try_stat.walk();
+    for clause in try_stat.children(&mut cursor) {
+        if clause.kind() != "catch_clause" {
+            continue;
+        }
+        let param = clause
+            .child_by_field_name("parameters")
+            .ok_or(Error::ChildNotFound)?
+            .utf8_text(content)?;
+        // (const std::exception& e) -> const std::exception& e
+        let param = param
+            .strip_prefix('(')
+            .and_then(|x| x.strip_suffix(')'))
+            .unwrap_or(param)
+            .trim();
+        let handler = clause
+            .child_by_field_name("body")
+            .ok_or(Error::ChildNotFound)?;
+        handlers.push((param.to_owned(), parse_stat(handler, content)?));
+    }
+    Ok(Rc::new(RefCell::new(Ast::new(
+        AstNode::Try { body, handlers },
+        try_stat.byte_range(),
+        None,
+    ))))
+}
+
 fn parse_do_while_stat(do_while_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     let condition = do_while_stat
         .child_by_field_name("condition")
@@ -398,6 +1161,7 @@ fn parse_do_while_stat(do_while_stat: Node, content: &[u8]) -> Result<Rc<RefCell
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::DoWhile {
             cond: String::from(cond_str),
+            cond_range: condition.byte_range(),
             body,
         },
         do_while_stat.byte_range(),
@@ -412,6 +1176,8 @@ fn parse_for_stat(for_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     let init = for_stat.child_by_field_name("initializer");
     let cond = for_stat.child_by_field_name("condition");
     let update = for_stat.child_by_field_name("update");
+    // parts left out point at the whole statement
+    let range_of = |x: Option<Node>| x.map_or(for_stat.byte_range(), |x| x.byte_range());
     let mut init_str: String = String::new();
     let mut cond_str: String = String::from("true");
     let mut update_str: String = String::new();
@@ -433,8 +1199,11 @@ fn parse_for_stat(for_stat: Node, content: &[u8]) -> Result<Rc<RefCell<Ast>>> {
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::For {
             init: init_str,
+            init_range: range_of(init),
             cond: cond_str,
+            cond_range: range_of(cond),
             upd: update_str,
+            upd_range: range_of(update),
             body,
         },
         for_stat.byte_range(),
@@ -456,6 +1225,16 @@ fn parse_range_for_stat(range_for_stat: Node, content: &[u8]) -> Result<Rc<RefCe
     let body = range_for_stat
         .child_by_field_name("body")
         .ok_or(Error::ChildNotFound)?;
+    let init = range_for_stat
+        .child_by_field_name("initializer")
+        .map(|x| {
+            Ok::<_, Error>(Rc::new(RefCell::new(Ast::new(
+                AstNode::Stat(String::from(x.utf8_text(content)?)),
+                x.byte_range(),
+                None,
+            ))))
+        })
+        .transpose()?;
     let body = parse_stat(body, content)?;
     let type_text = ty.utf8_text(content)?;
     let init_text = declarator.utf8_text(content)?;
@@ -463,11 +1242,16 @@ fn parse_range_for_stat(range_for_stat: Node, content: &[u8]) -> Result<Rc<RefCe
     let real_init_text = format!("{init_text}_iter = {range_text}.begin()");
     let real_cond_text = format!("{init_text}_iter != {range_text}.end()");
     let real_update_text = format!("++{init_text}_iter");
+    // the iterator is made up, its parts all stand for `x : range`
+    let header = declarator.start_byte()..range.end_byte();
     let res = Rc::new(RefCell::new(Ast::new(
         AstNode::For {
             init: real_init_text,
+            init_range: header.clone(),
             cond: real_cond_text,
+            cond_range: header.clone(),
             upd: real_update_text,
+            upd_range: header,
             body: Rc::new(RefCell::new(Ast::new(
                 AstNode::Compound(vec![
                     Rc::new(RefCell::new(Ast::new(
@@ -484,5 +1268,5 @@ fn parse_range_for_stat(range_for_stat: Node, content: &[u8]) -> Result<Rc<RefCe
         range_for_stat.byte_range(),
         None,
     )));
-    Ok(res)
+    Ok(with_init(init, res, range_for_stat.byte_range()))
 }
@@ -0,0 +1,154 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::error::{Error, Result};
+
+/// Flags forwarded to the C preprocessor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreprocessorOptions {
+    pub include_dirs: Vec<PathBuf>,
+    /// `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    pub undefines: Vec<String>,
+    /// Language standard, e.g. `c++17`
+    pub std: Option<String>,
+}
+
+impl PreprocessorOptions {
+    /// Pick the preprocessor flags out of a compiler command line.
+    /// Relative include directories are resolved against `directory`.
+    pub fn from_arguments(arguments: &[String], directory: &Path) -> Self {
+        let mut res = PreprocessorOptions::default();
+        let mut arguments = arguments.iter().map(String::as_str);
+        while let Some(argument) = arguments.next() {
+            // flags take their value either attached or as the next argument
+            let mut value = |flag: &str| match argument.strip_prefix(flag) {
+                Some("") => arguments.next().map(str::to_owned),
+                Some(value) => Some(value.to_owned()),
+                None => None,
+            };
+            if let Some(dir) = value("-isystem")
+                .or_else(|| value("-iquote"))
+                .or_else(|| value("-I"))
+            {
+                res.include_dirs.push(directory.join(dir));
+            } else if let Some(define) = value("-D") {
+                res.defines.push(define);
+            } else if let Some(undefine) = value("-U") {
+                res.undefines.push(undefine);
+            } else if let Some(std) = argument
+                .strip_prefix("-std=")
+                .or_else(|| argument.strip_prefix("--std="))
+            {
+                res.std = Some(std.to_owned());
+            }
+        }
+        res
+    }
+
+    /// Append the flags of `other`, which take precedence over the current ones
+    pub fn extend(&mut self, other: PreprocessorOptions) {
+        self.include_dirs.extend(other.include_dirs);
+        self.defines.extend(other.defines);
+        self.undefines.extend(other.undefines);
+        if other.std.is_some() {
+            self.std = other.std;
+        }
+    }
+
+    /// Command line arguments of `cpp`, C++ sources get `-x c++`
+    pub fn args(&self, path: Option<&Path>) -> Vec<String> {
+        let mut res = Vec::new();
+        let cxx_std = self.std.as_deref().is_some_and(|x| x.contains("++"));
+        let cxx_file = path
+            .and_then(|x| x.extension())
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| !matches!(x, "c" | "h"));
+        if cxx_std || cxx_file {
+            res.extend(["-x".to_owned(), "c++".to_owned()]);
+        }
+        if let Some(std) = &self.std {
+            res.push(format!("-std={std}"));
+        }
+        res.extend(
+            self.include_dirs
+                .iter()
+                .map(|x| format!("-I{}", x.display())),
+        );
+        res.extend(self.defines.iter().map(|x| format!("-D{x}")));
+        res.extend(self.undefines.iter().map(|x| format!("-U{x}")));
+        res
+    }
+}
+
+/// Run `cpp` on the file `path`, or on `content` if there is none, and map the result back
+/// to the lines of the input with [`follow_line_markers`].
+pub fn preprocess(
+    options: &PreprocessorOptions,
+    path: Option<&Path>,
+    content: &[u8],
+) -> Result<String> {
+    let mut cpp = Command::new("cpp");
+    cpp.args(options.args(path));
+    // on the real file, quoted includes are looked up next to it
+    match path {
+        Some(path) => cpp.arg(path),
+        None => cpp.arg("-").stdin(Stdio::piped()),
+    };
+    let mut cpp = cpp.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    if let Some(mut child_stdin) = cpp.stdin.take() {
+        child_stdin.write_all(content)?;
+    }
+    let output = cpp.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::PreprocessorFailed {
+            stderr: String::from_utf8_lossy(&output.stderr)
+                .trim_end()
+                .to_owned(),
+        });
+    }
+    Ok(follow_line_markers(&String::from_utf8(output.stdout)?))
+}
+
+/// Parse `# 42 "file.c" 1` or `#line 42 "file.c"` into the line number and file name
+fn line_marker(text: &str) -> Option<(usize, &str)> {
+    let rest = text.strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("line").unwrap_or(rest).trim_start();
+    let (number, rest) = rest.split_once(' ')?;
+    let file = rest.trim_start().strip_prefix('"')?;
+    Some((number.parse().ok()?, &file[..file.find('"')?]))
+}
+
+/// Keep the lines of preprocessor output which come from the input itself, each at the line
+/// it had in the input, so that spans point into the original file.
+///
+/// The input is the file named by the first line marker; included files are dropped.
+pub fn follow_line_markers(output: &str) -> String {
+    let mut main_file = None;
+    let mut in_main = true;
+    let mut line = 1;
+    let mut lines: Vec<String> = Vec::new();
+    for text in output.lines() {
+        if let Some((number, file)) = line_marker(text) {
+            in_main = *main_file.get_or_insert(file) == file;
+            line = number.max(1);
+            continue;
+        }
+        if in_main && !text.is_empty() {
+            if lines.len() < line {
+                lines.resize(line, String::new());
+            }
+            let slot = &mut lines[line - 1];
+            if !slot.is_empty() {
+                // a #line directive went back, keep both texts on the line they are marked with
+                slot.push(' ');
+            }
+            slot.push_str(text);
+        }
+        line += 1;
+    }
+    lines.join("\n")
+}
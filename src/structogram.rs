@@ -0,0 +1,504 @@
+//! Nassi–Shneiderman diagrams, drawn from the AST rather than the flow graph
+
+use std::{cell::RefCell, rc::Rc};
+
+use miette::NamedSource;
+
+use crate::{
+    ast::{Ast, AstNode},
+    display::{
+        RenderOptions,
+        svg::{CHAR_WIDTH, FONT_SIZE, LINE_HEIGHT, PADDING_X, PADDING_Y, TITLE_HEIGHT, escape},
+    },
+    error::{Error, Result},
+    layout::Size,
+};
+
+/// left margin of loop and try bodies
+const INDENT: f64 = 24.0;
+/// width of the arrow marking break, continue, return and throw
+const EXIT_WIDTH: f64 = 12.0;
+const MARGIN: f64 = 10.0;
+
+type Sequence = Vec<Rc<RefCell<Ast>>>;
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+}
+
+struct Context<'a> {
+    source: &'a str,
+    file_name: &'a str,
+    options: &'a RenderOptions,
+    res: String,
+}
+
+fn text_size(text: &str) -> Size {
+    let lines = text.lines().count().max(1) as f64;
+    let chars = text.lines().map(|x| x.chars().count()).max().unwrap_or(0) as f64;
+    Size {
+        width: chars * CHAR_WIDTH + 2.0 * PADDING_X,
+        height: lines * LINE_HEIGHT + 2.0 * PADDING_Y,
+    }
+}
+
+fn loop_header(node: &AstNode) -> Option<String> {
+    match node {
+        AstNode::While { cond, .. } | AstNode::DoWhile { cond, .. } => {
+            Some(format!("while {cond}"))
+        }
+        AstNode::For {
+            init, cond, upd, ..
+        } => {
+            // declarations keep their own semicolon
+            let init = init.trim_end().trim_end_matches(';');
+            Some(format!("for ({init}; {cond}; {upd})"))
+        }
+        _ => None,
+    }
+}
+
+/// split a switch body into (case labels, statements), dropping the break which ends a case
+fn switch_cases(cases: &[String], body: &Ast) -> Vec<(String, Sequence)> {
+    let AstNode::Compound(stats) = &body.node else {
+        return Vec::new();
+    };
+    let mut groups: Vec<(Vec<String>, Sequence)> = Vec::new();
+    for stat in stats {
+        let labels: Vec<String> = stat
+            .borrow()
+            .label
+            .iter()
+            .flatten()
+            .filter(|x| cases.contains(x))
+            .cloned()
+            .collect();
+        if !labels.is_empty() {
+            groups.push((labels, Vec::new()));
+        }
+        // statements before the first case can never run
+        if let Some((_, group)) = groups.last_mut() {
+            group.push(stat.clone());
+        }
+    }
+    // trailing labels without statements, e.g. an empty default
+    let seen: Vec<&String> = groups.iter().flat_map(|(x, _)| x).collect();
+    let rest: Vec<String> = cases
+        .iter()
+        .filter(|x| !seen.contains(x))
+        .cloned()
+        .collect();
+    if !rest.is_empty() {
+        groups.push((rest, Vec::new()));
+    }
+    let count = groups.len();
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (labels, mut group))| {
+            let last = group.last().map(|x| {
+                let x = x.borrow();
+                (
+                    matches!(x.node, AstNode::Break(_)),
+                    matches!(
+                        x.node,
+                        AstNode::Break(_)
+                            | AstNode::Continue(_)
+                            | AstNode::Return(_)
+                            | AstNode::Throw(_)
+                    ),
+                    x.range.clone(),
+                )
+            });
+            match last {
+                Some((true, _, _)) => {
+                    group.pop();
+                }
+                Some((false, false, range)) if idx + 1 < count => {
+                    group.push(Rc::new(RefCell::new(Ast::new(
+                        AstNode::Stat("(fall through)".to_owned()),
+                        range,
+                        None,
+                    ))));
+                }
+                _ => {}
+            }
+            (labels.join(", "), group)
+        })
+        .collect()
+}
+
+impl Context<'_> {
+    fn push(&mut self, str: String) {
+        self.res.push_str(str.as_str());
+    }
+
+    fn rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        self.push(format!(
+            "<rect class=\"block\" x=\"{x:.1}\" y=\"{y:.1}\" width=\"{w:.1}\" height=\"{h:.1}\"/>\n"
+        ));
+    }
+
+    fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        self.push(format!(
+            "<line class=\"line\" x1=\"{x1:.1}\" y1=\"{y1:.1}\" x2=\"{x2:.1}\" y2=\"{y2:.1}\"/>\n"
+        ));
+    }
+
+    /// write `text` vertically centered in the band `y..y + h`
+    fn text(&mut self, text: &str, x: f64, y: f64, w: f64, h: f64, align: Align) {
+        let lines: Vec<&str> = text.lines().collect();
+        let first = y + h / 2.0 - (lines.len().max(1) as f64 - 1.0) * LINE_HEIGHT / 2.0;
+        let (x, anchor) = match align {
+            Align::Left => (x + PADDING_X, "start"),
+            Align::Center => (x + w / 2.0, "middle"),
+        };
+        self.push(format!(
+            "<text x=\"{x:.1}\" y=\"{first:.1}\" text-anchor=\"{anchor}\" dominant-baseline=\"central\">"
+        ));
+        for (i, line) in lines.iter().enumerate() {
+            self.push(format!(
+                "<tspan x=\"{:.1}\" y=\"{:.1}\" xml:space=\"preserve\">{}</tspan>",
+                x,
+                first + i as f64 * LINE_HEIGHT,
+                escape(line)
+            ));
+        }
+        self.res.push_str("</text>\n");
+    }
+
+    fn measure(&self, ast: &Ast) -> Result<Size> {
+        match &ast.node {
+            AstNode::Dummy => Ok(Size {
+                width: 0.0,
+                height: 0.0,
+            }),
+            AstNode::Compound(v) => self.measure_sequence(v),
+            AstNode::Stat(s) | AstNode::Suspend(s) => Ok(text_size(s)),
+            AstNode::Break(s) | AstNode::Continue(s) | AstNode::Return(s) | AstNode::Throw(s) => {
+                let size = text_size(s);
+                Ok(Size {
+                    width: size.width + EXIT_WIDTH,
+                    ..size
+                })
+            }
+            AstNode::If {
+                cond,
+                body,
+                otherwise,
+                ..
+            } => self.measure_branches(
+                &self.options.choice(cond),
+                &[
+                    (self.options.yes.clone(), vec![body.clone()]),
+                    (self.options.no.clone(), otherwise.iter().cloned().collect()),
+                ],
+            ),
+            AstNode::Switch {
+                cond, cases, body, ..
+            } => self.measure_branches(cond, &switch_cases(cases, &body.borrow())),
+            AstNode::While { body, .. }
+            | AstNode::DoWhile { body, .. }
+            | AstNode::For { body, .. } => {
+                let header = loop_header(&ast.node).unwrap_or_default();
+                self.measure_sections(&[(header, vec![body.clone()])])
+            }
+            AstNode::Try { body, handlers } => self.measure_sections(&try_sections(body, handlers)),
+            AstNode::Goto(_) => Err(Error::UnstructuredGoto {
+                src: NamedSource::new(self.file_name, self.source.to_string()),
+                range: ast.range.clone().into(),
+            }),
+        }
+    }
+
+    fn measure_sequence(&self, v: &[Rc<RefCell<Ast>>]) -> Result<Size> {
+        if v.is_empty() {
+            return Ok(text_size(""));
+        }
+        let mut res = Size {
+            width: 0.0,
+            height: 0.0,
+        };
+        for i in v {
+            let size = self.measure(&i.borrow())?;
+            res.width = res.width.max(size.width);
+            res.height += size.height;
+        }
+        Ok(res)
+    }
+
+    /// loops and try blocks: a header above an indented body
+    fn measure_sections(&self, sections: &[(String, Sequence)]) -> Result<Size> {
+        let mut res = Size {
+            width: 0.0,
+            height: 0.0,
+        };
+        for (header, body) in sections {
+            let header = text_size(header);
+            let body = self.measure_sequence(body)?;
+            res.width = res.width.max(header.width).max(body.width + INDENT);
+            res.height += header.height + body.height;
+        }
+        Ok(res)
+    }
+
+    fn measure_branches(&self, cond: &str, columns: &[(String, Sequence)]) -> Result<Size> {
+        let cond = text_size(cond);
+        let header = cond.height + LINE_HEIGHT;
+        let mut width = 0.0;
+        let mut height: f64 = 0.0;
+        for (label, body) in columns {
+            let size = self.measure_sequence(body)?;
+            width += size.width.max(text_size(label).width);
+            height = height.max(size.height);
+        }
+        // the condition sits in the triangle above the diagonals
+        Ok(Size {
+            width: f64::max(width, cond.width * header / LINE_HEIGHT),
+            height: header + height,
+        })
+    }
+
+    fn draw(&mut self, ast: &Ast, x: f64, y: f64, w: f64, h: f64) -> Result<()> {
+        match &ast.node {
+            AstNode::Dummy => {}
+            AstNode::Compound(v) => self.draw_sequence(v, x, y, w, h)?,
+            AstNode::Stat(s) | AstNode::Suspend(s) => {
+                self.rect(x, y, w, h);
+                self.text(s, x, y, w, h, Align::Left);
+            }
+            AstNode::Break(s) | AstNode::Continue(s) | AstNode::Return(s) | AstNode::Throw(s) => {
+                self.rect(x, y, w, h);
+                self.push(format!(
+                    "<polyline class=\"line\" points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"/>\n",
+                    x + EXIT_WIDTH,
+                    y,
+                    x,
+                    y + h / 2.0,
+                    x + EXIT_WIDTH,
+                    y + h
+                ));
+                self.text(s, x + EXIT_WIDTH, y, w - EXIT_WIDTH, h, Align::Left);
+            }
+            AstNode::If {
+                cond,
+                body,
+                otherwise,
+                ..
+            } => self.draw_branches(
+                &self.options.choice(cond),
+                &[
+                    (self.options.yes.clone(), vec![body.clone()]),
+                    (self.options.no.clone(), otherwise.iter().cloned().collect()),
+                ],
+                x,
+                y,
+                w,
+                h,
+            )?,
+            AstNode::Switch {
+                cond, cases, body, ..
+            } => {
+                let columns = switch_cases(cases, &body.borrow());
+                self.draw_branches(cond, &columns, x, y, w, h)?
+            }
+            AstNode::While { body, .. } | AstNode::For { body, .. } => {
+                let header = loop_header(&ast.node).unwrap_or_default();
+                self.draw_sections(&[(header, vec![body.clone()])], x, y, w, h)?
+            }
+            AstNode::DoWhile { body, .. } => {
+                // the condition is checked after the body
+                let header = loop_header(&ast.node).unwrap_or_default();
+                let footer = text_size(&header).height;
+                self.rect(x, y, w, h);
+                self.draw(&body.borrow(), x + INDENT, y, w - INDENT, h - footer)?;
+                self.text(&header, x, y + h - footer, w, footer, Align::Left);
+            }
+            AstNode::Try { body, handlers } => {
+                self.draw_sections(&try_sections(body, handlers), x, y, w, h)?
+            }
+            AstNode::Goto(_) => {
+                return Err(Error::UnstructuredGoto {
+                    src: NamedSource::new(self.file_name, self.source.to_string()),
+                    range: ast.range.clone().into(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// stack `v` from top to bottom, the last block takes up the remaining height
+    fn draw_sequence(
+        &mut self,
+        v: &[Rc<RefCell<Ast>>],
+        x: f64,
+        mut y: f64,
+        w: f64,
+        h: f64,
+    ) -> Result<()> {
+        if v.is_empty() {
+            self.rect(x, y, w, h);
+            return Ok(());
+        }
+        let bottom = y + h;
+        for (idx, i) in v.iter().enumerate() {
+            let height = if idx + 1 == v.len() {
+                bottom - y
+            } else {
+                self.measure(&i.borrow())?.height
+            };
+            self.draw(&i.borrow(), x, y, w, height)?;
+            y += height;
+        }
+        Ok(())
+    }
+
+    fn draw_sections(
+        &mut self,
+        sections: &[(String, Sequence)],
+        x: f64,
+        mut y: f64,
+        w: f64,
+        h: f64,
+    ) -> Result<()> {
+        let bottom = y + h;
+        self.rect(x, y, w, h);
+        for (idx, (header, body)) in sections.iter().enumerate() {
+            let header_height = text_size(header).height;
+            let body_height = if idx + 1 == sections.len() {
+                bottom - y - header_height
+            } else {
+                self.measure_sequence(body)?.height
+            };
+            self.text(header, x, y, w, header_height, Align::Left);
+            self.draw_sequence(body, x + INDENT, y + header_height, w - INDENT, body_height)?;
+            if idx > 0 {
+                self.line(x, y, x + INDENT, y);
+            }
+            y += header_height + body_height;
+        }
+        Ok(())
+    }
+
+    fn draw_branches(
+        &mut self,
+        cond: &str,
+        columns: &[(String, Sequence)],
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+    ) -> Result<()> {
+        let cond_height = text_size(cond).height;
+        let header = cond_height + LINE_HEIGHT;
+        let mut widths = Vec::new();
+        for (label, body) in columns {
+            widths.push(
+                self.measure_sequence(body)?
+                    .width
+                    .max(text_size(label).width),
+            );
+        }
+        // spread the spare width over the columns
+        let total: f64 = widths.iter().sum();
+        let widths: Vec<f64> = widths.iter().map(|x| x * w / total.max(1.0)).collect();
+        let split = x + w - widths.last().copied().unwrap_or(w);
+        self.rect(x, y, w, h);
+        self.line(x, y, split, y + header);
+        self.line(x + w, y, split, y + header);
+        self.line(x, y + header, x + w, y + header);
+        self.text(cond, x, y, w, cond_height, Align::Center);
+        let mut left = x;
+        for (idx, ((label, body), width)) in columns.iter().zip(&widths).enumerate() {
+            if idx > 0 && left < split {
+                // column borders reach up to the left diagonal
+                let top = y + header * (left - x) / (split - x).max(1.0);
+                self.line(left, top, left, y + header);
+            }
+            self.push(format!(
+                "<text class=\"label\" x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                left + width / 2.0,
+                y + cond_height + LINE_HEIGHT / 2.0,
+                escape(label)
+            ));
+            self.draw_sequence(body, left, y + header, *width, h - header)?;
+            left += width;
+        }
+        Ok(())
+    }
+}
+
+fn try_sections(
+    body: &Rc<RefCell<Ast>>,
+    handlers: &[(String, Rc<RefCell<Ast>>)],
+) -> Vec<(String, Sequence)> {
+    let mut res = vec![("try".to_owned(), vec![body.clone()])];
+    for (param, handler) in handlers {
+        res.push((format!("catch ({param})"), vec![handler.clone()]));
+    }
+    res
+}
+
+/// Render structograms of `functions`, stacked vertically with their names as titles
+/// if there is more than one.
+pub fn render(
+    functions: &[(String, Rc<RefCell<Ast>>)],
+    source: &str,
+    file_name: &str,
+    options: &RenderOptions,
+) -> Result<String> {
+    let mut ctx = Context {
+        source,
+        file_name,
+        options,
+        res: String::new(),
+    };
+    let titled = functions.len() > 1;
+    let (mut width, mut height) = (0.0, MARGIN);
+    for (name, ast) in functions {
+        if titled {
+            ctx.push(format!(
+                "<text class=\"title\" x=\"{:.1}\" y=\"{:.1}\">{}</text>\n",
+                MARGIN,
+                height + TITLE_HEIGHT - 8.0,
+                escape(name)
+            ));
+            height += TITLE_HEIGHT;
+        }
+        let size = ctx.measure(&ast.borrow())?;
+        ctx.draw(&ast.borrow(), MARGIN, height, size.width, size.height)?;
+        width = f64::max(width, size.width + 2.0 * MARGIN);
+        height += size.height + MARGIN;
+    }
+    // the layout is measured in the default font size, so only the `all` style of nodes,
+    // the font family and colors of the theme apply
+    let theme = &options.theme;
+    let style = &theme.nodes.all;
+    let family = theme.font.family.as_deref().unwrap_or("monospace");
+    let text = style
+        .text
+        .as_ref()
+        .or(theme.font.color.as_ref())
+        .map(|x| format!(" fill: {};", x.hex()))
+        .unwrap_or_default();
+    let fill = style.fill.as_ref().map_or("white", |x| x.hex());
+    let stroke = style.stroke.as_ref().map_or("black", |x| x.hex());
+    let stroke_width = style.width.unwrap_or(1.5);
+    let background = theme.background.as_ref().map_or("white", |x| x.hex());
+    Ok(format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.0}" height="{height:.0}" viewBox="0 0 {width:.1} {height:.1}">
+<style>
+text {{ font-family: {family}; font-size: {FONT_SIZE}px;{text} }}
+.block {{ fill: {fill}; stroke: {stroke}; stroke-width: {stroke_width}; }}
+.line {{ fill: none; stroke: {stroke}; stroke-width: {stroke_width}; }}
+.label {{ font-size: 12px; }}
+.title {{ font-weight: bold; }}
+</style>
+<rect width="100%" height="100%" fill="{background}"/>
+{}</svg>
+"#,
+        ctx.res
+    ))
+}
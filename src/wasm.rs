@@ -1,8 +1,10 @@
 use std::{cell::RefCell, ptr, slice, str};
 
 use crate::{
-    display::{GraphDisplayBackend, dot::Dot},
-    generate,
+    blank_directives,
+    conditional::Macros,
+    display::{GraphDisplayBackend, dot::Dot, svg::Svg},
+    generate, list, list_functions,
 };
 
 thread_local! {
@@ -34,14 +36,21 @@ fn read_bytes(ptr: *const u8, len: usize) -> Result<&'static [u8], String> {
     Ok(unsafe { slice::from_raw_parts(ptr, len) })
 }
 
-fn generate_dot_inner(
+/// The source text with its directives blanked out, as the command line does
+fn read_source(ptr: *const u8, len: usize) -> Result<String, String> {
+    let content = str::from_utf8(read_bytes(ptr, len)?)
+        .map_err(|err| format!("invalid UTF-8 in source: {err}"))?;
+    Ok(blank_directives(content, &Macros::predefined(None, None)))
+}
+
+fn generate_inner(
     content_ptr: *const u8,
     content_len: usize,
     function_ptr: *const u8,
     function_len: usize,
-    curly: bool,
+    backend: GraphDisplayBackend,
 ) -> Result<String, String> {
-    let content = read_bytes(content_ptr, content_len)?;
+    let content = read_source(content_ptr, content_len)?;
     let function = read_bytes(function_ptr, function_len)?;
     let function_name = if function.is_empty() {
         None
@@ -53,13 +62,27 @@ fn generate_dot_inner(
         )
     };
 
-    generate(
-        content,
-        "input.cpp",
-        function_name,
-        GraphDisplayBackend::Dot(Dot::new(curly)),
-    )
-    .map_err(|err| err.to_string())
+    generate(content.as_bytes(), "input.cpp", function_name, backend).map_err(|err| err.to_string())
+}
+
+fn store_result(result: std::thread::Result<Result<String, String>>) -> i32 {
+    match result {
+        Ok(Ok(res)) => {
+            set_last_result(res.into_bytes());
+            set_last_error(Vec::new());
+            0
+        }
+        Ok(Err(err)) => {
+            set_last_result(Vec::new());
+            set_last_error(err.into_bytes());
+            1
+        }
+        Err(_) => {
+            set_last_result(Vec::new());
+            set_last_error(b"panic while generating flowchart".to_vec());
+            2
+        }
+    }
 }
 
 #[unsafe(no_mangle)]
@@ -92,31 +115,43 @@ pub extern "C" fn cxx2flow_generate_dot(
     function_len: usize,
     curly: u32,
 ) -> i32 {
-    match std::panic::catch_unwind(|| {
-        generate_dot_inner(
+    store_result(std::panic::catch_unwind(|| {
+        generate_inner(
             content_ptr,
             content_len,
             function_ptr,
             function_len,
-            curly != 0,
+            GraphDisplayBackend::Dot(Dot::new(curly != 0)),
         )
-    }) {
-        Ok(Ok(dot)) => {
-            set_last_result(dot.into_bytes());
-            set_last_error(Vec::new());
-            0
-        }
-        Ok(Err(err)) => {
-            set_last_result(Vec::new());
-            set_last_error(err.into_bytes());
-            1
-        }
-        Err(_) => {
-            set_last_result(Vec::new());
-            set_last_error(b"panic while generating flowchart".to_vec());
-            2
-        }
-    }
+    }))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn cxx2flow_generate_svg(
+    content_ptr: *const u8,
+    content_len: usize,
+    function_ptr: *const u8,
+    function_len: usize,
+) -> i32 {
+    store_result(std::panic::catch_unwind(|| {
+        generate_inner(
+            content_ptr,
+            content_len,
+            function_ptr,
+            function_len,
+            GraphDisplayBackend::Svg(Svg::new()),
+        )
+    }))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn cxx2flow_list_functions(content_ptr: *const u8, content_len: usize) -> i32 {
+    store_result(std::panic::catch_unwind(|| {
+        let content = read_source(content_ptr, content_len)?;
+        list_functions(content.as_bytes(), "input.cpp")
+            .and_then(|functions| list::to_json(&functions))
+            .map_err(|err| err.to_string())
+    }))
 }
 
 #[unsafe(no_mangle)]
@@ -0,0 +1,44 @@
+//! Running again whenever the input changes, for `--watch`.
+
+use std::{fmt::Debug, io, path::Path, sync::mpsc::Receiver, time::Duration};
+
+/// Write `content` to `path`, unless the file already holds it, so that whatever watches the
+/// output only sees real changes. Returns whether the file was written.
+pub fn write_if_changed(path: impl AsRef<Path>, content: &str) -> io::Result<bool> {
+    if std::fs::read(&path).is_ok_and(|x| x == content.as_bytes()) {
+        return Ok(false);
+    }
+    std::fs::write(path, content)?;
+    Ok(true)
+}
+
+/// Call `run`, then again after every burst of events touching a path for which `changed`
+/// holds. Errors of `run` are printed to stderr and do not stop watching.
+///
+/// Returns once the sending side of `receiver` is gone.
+pub fn run_on_changes<E: Debug>(
+    receiver: &Receiver<notify::Result<notify::Event>>,
+    changed: impl Fn(&Path) -> bool,
+    mut run: impl FnMut() -> Result<(), E>,
+) {
+    loop {
+        if let Err(error) = run() {
+            eprintln!("{error:?}");
+        }
+        loop {
+            match receiver.recv() {
+                Ok(Ok(event))
+                    if !event.kind.is_access() && event.paths.iter().any(|x| changed(x)) =>
+                {
+                    break;
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(error)) => eprintln!("{:?}", miette::Report::msg(error)),
+                Err(_) => return,
+            }
+        }
+        // a single save comes as a burst of events
+        std::thread::sleep(Duration::from_millis(100));
+        while receiver.try_recv().is_ok() {}
+    }
+}
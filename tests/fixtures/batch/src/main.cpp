@@ -0,0 +1,9 @@
+#include "util/helper.h"
+
+int main() {
+  int x = helper(3);
+  if (x > 2) {
+    x--;
+  }
+  return x;
+}
@@ -0,0 +1,32 @@
+#include <cstdio>
+
+#define LOG_LEVEL 2
+#define TRACE(fmt, ...) \
+    std::printf(fmt, __VA_ARGS__)
+
+int handle(int request) {
+    int status = 0;
+#ifdef FEATURE_CACHE
+    if (cache_hit(request)) {
+        return cached(request);
+    }
+#endif
+    #if LOG_LEVEL > 1 && !defined(NDEBUG)
+    log_request(request);
+    #elif LOG_LEVEL > 0
+    count_request(request);
+    #else
+    /* logging is off */
+    #endif
+    while (status == 0) {
+#if defined(FEATURE_RETRY) && RETRIES >= 3
+        status = retry(request, RETRIES);
+#ifndef NDEBUG
+        report_retry(request);
+#endif
+#else
+        status = process(request);
+#endif
+    }
+    return status;
+}
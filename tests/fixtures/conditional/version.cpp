@@ -0,0 +1,13 @@
+int version() {
+#if __cplusplus >= 202002L
+    return 20;
+#elif __cplusplus >= 201703L
+    return 17;
+#elif defined(__cplusplus)
+    return 11;
+#elif __STDC_VERSION__ >= 201112L
+    return 2011;
+#else
+    return 0;
+#endif
+}
@@ -0,0 +1,10 @@
+int main() {
+  switch (mode()) {
+  case 1:
+    run();
+    break;
+  default:
+    stop();
+  }
+  return 0;
+}
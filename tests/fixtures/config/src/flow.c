@@ -0,0 +1,6 @@
+int main() {
+  if (ready()) {
+    run();
+  }
+  return 0;
+}
@@ -0,0 +1,16 @@
+struct Box {
+  int get() const {
+    return value;
+  }
+  int get() {
+    touched = true;
+    return value;
+  }
+  int take() && {
+    int res = value;
+    value = 0;
+    return res;
+  }
+  int value;
+  bool touched;
+};
@@ -0,0 +1,26 @@
+# 0 "src/main.c"
+# 0 "<built-in>"
+# 0 "<command-line>"
+# 1 "/usr/include/stdc-predef.h" 1 3 4
+# 0 "<command-line>" 2
+# 1 "src/main.c"
+# 1 "src/helper.h" 1
+int helper(int n);
+
+static int twice(int n) {
+  return n * 2;
+}
+# 2 "src/main.c" 2
+
+
+
+
+int main() {
+  int x = helper(((5) * 2));
+  if (x >
+      2) {
+    x--;
+  }
+# 16 "src/main.c"
+  return twice(x);
+}
@@ -0,0 +1,8 @@
+# 1 "src/main.c"
+int main() {
+  int x = 0;
+  x++;
+# 3 "src/main.c"
+  x *= 2;
+  return x;
+}
@@ -0,0 +1,34 @@
+#include <map>
+
+template <bool Fast>
+int pick(std::map<int, int> &m, int k) {
+  if constexpr (Fast) {
+    return m[k];
+  }
+  if constexpr (false) {
+    trace(k);
+  } else {
+    count(k);
+  }
+  if ! consteval {
+    trace(k);
+  }
+  if consteval{
+    count(k);
+  }
+  if consteval {
+    return 0;
+  } else if (auto it = m.find(k); it != m.end()) {
+    return it->second;
+  }
+  switch (int v = k % 3; v) {
+  case 0:
+    return 1;
+  default:
+    break;
+  }
+  for (auto items = m; auto entry : items) {
+    k += entry.second;
+  }
+  return k;
+}
@@ -0,0 +1,13 @@
+task<int> fetch(connection &conn, int retries) {
+  auto on_line = [&](auto line) -> task<void> { co_await log(line); };
+  while (retries-- > 0) {
+    auto reply = co_await conn.read();
+    if (!reply) {
+      co_await sleep(backoff);
+      continue;
+    }
+    co_yield reply->size();
+    co_return reply->status();
+  }
+  co_return -1;
+}
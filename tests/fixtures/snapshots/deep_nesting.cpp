@@ -0,0 +1,31 @@
+int main() {
+  int total = 0;
+  for (int i = 0; i < n; ++i) {
+    if (a[i] > 0) {
+      while (x) {
+        x--;
+        if (x == 3) {
+          continue;
+        }
+      }
+    } else if (a[i] < 0) {
+      do {
+        retry:
+        y++;
+      } while (y < 3);
+    } else {
+      switch (i) {
+      case 1:
+        total++;
+        break;
+      default:
+        goto done;
+      }
+    }
+  }
+  if (total == 0) {
+    goto retry;
+  }
+done:
+  return total;
+}
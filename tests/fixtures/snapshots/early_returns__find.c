@@ -0,0 +1,15 @@
+int find(const int *v, int n, int key) {
+  if (n == 0)
+    return -1;
+  for (int i = 0; i < n; i++) {
+    if (v[i] < 0)
+      continue;
+    if (v[i] == key)
+      goto found;
+    if (v[i] > key)
+      break;
+  }
+  return -1;
+found:
+  return key;
+}
@@ -0,0 +1,20 @@
+int main() {
+  int limit = 3;
+  auto cmp = [&](int a, int b) {
+    if (a > limit) {
+      return false;
+    }
+    return a < b;
+  };
+  sort(v.begin(), v.end(), [](int a, int b) { return a > b; });
+  auto outer = [](int n) {
+    auto inner = [](int x) {
+      while (x) {
+        x--;
+      }
+      return x;
+    };
+    return inner(n);
+  };
+  return cmp(1, 2) + outer(limit);
+}
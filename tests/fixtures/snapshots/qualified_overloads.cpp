@@ -0,0 +1,38 @@
+namespace ns {
+struct Counter {
+  int run() { return count; }
+  int run(int step) {
+    if (step > 0) {
+      count += step;
+    }
+    return count;
+  }
+  ~Counter() { count = 0; }
+  bool operator==(const Counter &other) const { return count == other.count; }
+  int count;
+};
+
+int run() { return 1; }
+} // namespace ns
+
+struct Foo {
+  void bar();
+};
+
+void Foo::bar() {
+  while (ready()) {
+    step();
+  }
+}
+
+int run(const char *name, int times = 3) {
+  for (int i = 0; i < times; i++) {
+    puts(name);
+  }
+  return times;
+}
+
+int main() {
+  ns::Counter c;
+  return c.run(2);
+}
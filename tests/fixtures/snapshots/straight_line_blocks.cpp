@@ -0,0 +1,15 @@
+int main() {
+  int total = 0;
+  int count = read_count();
+  total += count;
+  log_total(total,
+            count);
+  for (int i = 0; i < count; ++i) {
+    int value = read_value(i);
+    value *= 2;
+    total += value;
+  }
+  normalize(total);
+  report(total);
+  return total;
+}
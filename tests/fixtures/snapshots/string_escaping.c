@@ -0,0 +1,7 @@
+int main() {
+  char buf[16] = "#tag";
+  if (buf[0] == '#' && strcmp(buf, "{x}") != 0) {
+    printf("<%s> \"quoted\"\n", buf);
+  }
+  return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdexcept>
+
+int main() {
+  int v = 0;
+  try {
+    if (v < 0) {
+      throw std::runtime_error("negative");
+    }
+    try {
+      v = 1;
+      throw v;
+    } catch (int e) {
+      v = e;
+      throw;
+    }
+  } catch (const std::exception& e) {
+    v = -1;
+  } catch (...) {
+    v = -2;
+  }
+  if (v > 1) {
+    throw v;
+  }
+  return v;
+}
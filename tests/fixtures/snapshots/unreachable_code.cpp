@@ -0,0 +1,19 @@
+int main() {
+    int count = 0;
+    while (true) {
+        count = next(count);
+        if (count > 10) {
+            break;
+        }
+        continue;
+        count = 0;
+    }
+    if (0) {
+        debug(count);
+    }
+    return count;
+    count = -1;
+    for (int i = 0; i < count; ++i) {
+        log(i);
+    }
+}
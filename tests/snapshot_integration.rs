@@ -1,13 +1,30 @@
 use std::{
     fs,
     path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, SystemTime},
 };
 
 use cxx2flow_lib::{
-    display::{GraphDisplayBackend, d2::D2, dot::Dot, tikz::Tikz},
-    generate,
+    GraphOptions, LambdaMode, SwitchMode, UnreachableMode,
+    batch::Batch,
+    blank_directives,
+    cli::Args,
+    conditional::Macros,
+    config::{Config, Settings},
+    display::{
+        GraphDisplayBackend, Locale, RenderOptions, d2::D2, dot::Dot, json::Json, mermaid::Mermaid,
+        svg::Svg, theme::Theme, tikz::Tikz,
+    },
+    generate, generate_combined, generate_structogram, generate_with_options, list_functions, lsp,
+    metrics, metrics_all,
+    preprocess::{PreprocessorOptions, follow_line_markers},
+    unreachable_code, watch,
 };
 use libtest_mimic::{Arguments, Failed, Trial};
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use miette::{GraphicalReportHandler, GraphicalTheme};
+use serde_json::{Value, json};
 
 #[derive(Clone, Debug)]
 struct FixtureCase {
@@ -17,12 +34,29 @@ struct FixtureCase {
     source: Vec<u8>,
 }
 
+#[derive(Clone, Copy)]
+struct ConfigCase {
+    snapshot_name: &'static str,
+    flags: &'static [&'static str],
+    input: &'static str,
+}
+
 #[derive(Clone, Copy)]
 enum BackendKind {
     DotPolyline,
     DotCurly,
     D2,
     Tikz,
+    Mermaid,
+    Json,
+    Svg,
+}
+
+#[derive(Clone, Copy)]
+struct SelectionCase {
+    snapshot_name: &'static str,
+    fixture_name: &'static str,
+    function: &'static str,
 }
 
 #[derive(Clone, Copy)]
@@ -56,6 +90,175 @@ const TIKZ_CASES: &[&str] = &[
     "class_method_and_main",
     "template_function",
     "range_for_initializer",
+    "try_throw_nested",
+    "coroutine_suspend",
+];
+
+const MERMAID_CASES: &[&str] = &[
+    "if_else",
+    "while_continue_break",
+    "switch_char_literal",
+    "lambda_in_statement",
+    "try_throw_nested",
+    "string_escaping",
+    "coroutine_suspend",
+];
+
+const JSON_CASES: &[&str] = &[
+    "if_else",
+    "switch_with_default",
+    "try_throw_nested",
+    "coroutine_suspend",
+];
+
+const SVG_CASES: &[&str] = &[
+    "if_else",
+    "while_simple",
+    "nested_loop_mix",
+    "goto_backward_label",
+    "try_throw_nested",
+    "coroutine_suspend",
+];
+
+const COMBINED_CASES: &[&str] = &["multi_function_pick_second", "class_method_and_main"];
+
+const METRICS_CASES: &[&str] = &[
+    "linear_return",
+    "nested_loop_mix",
+    "goto_backward_label",
+    "switch_with_default",
+    "try_throw_nested",
+    "unreachable_code",
+];
+
+const STRUCTOGRAM_CASES: &[&str] = &[
+    "if_else",
+    "do_while",
+    "nested_loop_mix",
+    "switch_with_default",
+    "switch_multiple_fallthrough",
+    "try_throw_nested",
+];
+
+const LAMBDA_CASES: &[&str] = &["lambda_in_statement", "nested_lambdas"];
+
+const LINK_CASES: &[&str] = &["if_else", "nested_loop_mix"];
+
+const UNREACHABLE_CASES: &[&str] = &["unreachable_code", "goto_forward_label"];
+
+const BASIC_BLOCK_CASES: &[&str] = &["straight_line_blocks", "nested_loop_mix"];
+
+const FOLD_CASES: &[&str] = &["deep_nesting", "nested_loop_mix"];
+
+const RENDER_OPTIONS_CASES: &[&str] = &["if_else", "switch_with_default"];
+
+const MULTI_WAY_CASES: &[&str] = &[
+    "switch_multiple_fallthrough",
+    "switch_default_middle",
+    "switch_without_default",
+    "switch_char_literal",
+    "switch_duffs_device",
+];
+
+const THEME_CASES: &[&str] = &["if_else", "try_catch_statements", "while_simple"];
+
+/// A theme setting every kind of field, next to the built-in ones
+const CUSTOM_THEME: &str = r##"
+background = "#fdf6e3"
+
+[font]
+family = "Fira Code"
+size = 11
+color = "#586e75"
+
+[nodes.all]
+stroke = "#93a1a1"
+width = 2
+
+[nodes.begin]
+shape = "ellipse"
+fill = "#eee8d5"
+
+[nodes.choice]
+shape = "hexagon"
+fill = "#b58900"
+text = "#fdf6e3"
+
+[nodes.loop]
+fill = "#2aa198"
+
+[nodes.return]
+stroke = "#cb4b16"
+
+[edges.exception]
+stroke = "#dc322f"
+dashed = true
+"##;
+
+const INVALID_THEME: &str = r##"
+[nodes.choice]
+shape = "hexagon"
+fill = "yellow"
+"##;
+
+const LIST_CASES: &[&str] = &["qualified_overloads", "class_method_and_main"];
+
+const SELECTION_CASES: &[SelectionCase] = &[
+    SelectionCase {
+        snapshot_name: "select__free_function",
+        fixture_name: "qualified_overloads",
+        function: "run",
+    },
+    SelectionCase {
+        snapshot_name: "select__namespace_function",
+        fixture_name: "qualified_overloads",
+        function: "ns::run",
+    },
+    SelectionCase {
+        snapshot_name: "select__overload_by_params",
+        fixture_name: "qualified_overloads",
+        function: "Counter::run(int)",
+    },
+    SelectionCase {
+        snapshot_name: "select__overload_without_params",
+        fixture_name: "qualified_overloads",
+        function: "ns::Counter::run()",
+    },
+    SelectionCase {
+        snapshot_name: "select__out_of_class_definition",
+        fixture_name: "qualified_overloads",
+        function: "Foo::bar",
+    },
+    SelectionCase {
+        snapshot_name: "select__destructor",
+        fixture_name: "qualified_overloads",
+        function: "~Counter",
+    },
+    SelectionCase {
+        snapshot_name: "select__operator",
+        fixture_name: "qualified_overloads",
+        function: "operator==",
+    },
+    SelectionCase {
+        snapshot_name: "select__params_with_default_value",
+        fixture_name: "qualified_overloads",
+        function: "::run(const char *, int)",
+    },
+    SelectionCase {
+        snapshot_name: "select__const_qualified",
+        fixture_name: "const_overloads",
+        function: "get() const",
+    },
+    SelectionCase {
+        snapshot_name: "select__unqualified_next_to_const",
+        fixture_name: "const_overloads",
+        function: "Box::get()",
+    },
+    SelectionCase {
+        snapshot_name: "select__ref_qualified",
+        fixture_name: "const_overloads",
+        function: "take() &&",
+    },
 ];
 
 const ERROR_CASES: &[ErrorCase] = &[
@@ -64,6 +267,26 @@ const ERROR_CASES: &[ErrorCase] = &[
         fixture_name: "linear_return",
         function: "missing_function",
     },
+    ErrorCase {
+        snapshot_name: "error__ambiguous_function_debug",
+        fixture_name: "qualified_overloads",
+        function: "Counter::run",
+    },
+    ErrorCase {
+        snapshot_name: "error__mismatched_qualifiers_debug",
+        fixture_name: "const_overloads",
+        function: "take() const",
+    },
+    ErrorCase {
+        snapshot_name: "error__misspelled_function_debug",
+        fixture_name: "namespace_function__solve",
+        function: "slove",
+    },
+    ErrorCase {
+        snapshot_name: "error__mismatched_params_debug",
+        fixture_name: "qualified_overloads",
+        function: "Foo::bar(int)",
+    },
     ErrorCase {
         snapshot_name: "error__unexpected_continue_debug",
         fixture_name: "unexpected_continue",
@@ -76,6 +299,34 @@ const ERROR_CASES: &[ErrorCase] = &[
     },
 ];
 
+const CONFIG_CASES: &[ConfigCase] = &[
+    ConfigCase {
+        snapshot_name: "config__defaults",
+        flags: &[],
+        input: "src/flow.c",
+    },
+    ConfigCase {
+        snapshot_name: "config__command_line_first",
+        flags: &["--svg", "--switches", "ladder", "-D", "CLI"],
+        input: "legacy/flow.c",
+    },
+    ConfigCase {
+        snapshot_name: "config__override",
+        flags: &[],
+        input: "legacy/flow.c",
+    },
+    ConfigCase {
+        snapshot_name: "config__profile",
+        flags: &["--profile", "docs"],
+        input: "legacy/flow.c",
+    },
+    ConfigCase {
+        snapshot_name: "config__requirements",
+        flags: &["-I", "extra", "--std", "c11", "--filter", "send*"],
+        input: "src/flow.c",
+    },
+];
+
 const KNOWN_BROKEN_CASES: &[&str] = &[];
 
 fn project_root() -> PathBuf {
@@ -93,6 +344,14 @@ fn error_fixtures_dir() -> PathBuf {
     project_root().join("tests").join("fixtures").join("errors")
 }
 
+fn batch_fixtures_dir() -> PathBuf {
+    project_root().join("tests").join("fixtures").join("batch")
+}
+
+fn config_fixtures_dir() -> PathBuf {
+    project_root().join("tests").join("fixtures").join("config")
+}
+
 fn split_fixture_stem(stem: &str) -> (String, String) {
     if let Some((name, function)) = stem.split_once("__") {
         (name.to_owned(), function.to_owned())
@@ -213,11 +472,795 @@ fn run_snapshot_case(case: FixtureCase, backend: BackendKind) -> Result<(), Fail
             format!("tikz__{}", case.name),
             render(&case, Tikz::new().into()),
         ),
+        BackendKind::Mermaid => (
+            format!("mermaid__{}", case.name),
+            render(&case, Mermaid::new().into()),
+        ),
+        BackendKind::Json => (
+            format!("json__{}", case.name),
+            render(&case, Json::new().into()),
+        ),
+        BackendKind::Svg => (
+            format!("svg__{}", case.name),
+            render(&case, Svg::new().into()),
+        ),
     };
     insta::assert_snapshot!(snapshot_name, output);
     Ok(())
 }
 
+fn run_combined_case(case: FixtureCase, backend: BackendKind) -> Result<(), Failed> {
+    let (prefix, backend): (&str, GraphDisplayBackend) = match backend {
+        BackendKind::DotPolyline => ("dot_polyline", Dot::new(false).into()),
+        BackendKind::DotCurly => ("dot_curly", Dot::new(true).into()),
+        BackendKind::D2 => ("d2", D2::new().into()),
+        BackendKind::Tikz => ("tikz", Tikz::new().into()),
+        BackendKind::Mermaid => ("mermaid", Mermaid::new().into()),
+        BackendKind::Json => ("json", Json::new().into()),
+        BackendKind::Svg => ("svg", Svg::new().into()),
+    };
+    let output = generate_combined(
+        &case.source,
+        "combined.cpp",
+        backend,
+        &GraphOptions::default(),
+    )
+    .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("combined_{prefix}__{}", case.name), output);
+    Ok(())
+}
+
+fn run_metrics_case(case: FixtureCase) -> Result<(), Failed> {
+    let report = metrics(&case.source, "metrics.cpp", Some(case.function.clone()))
+        .unwrap_or_else(|error| panic!("failed to compute metrics {}: {error:?}", case.name));
+    let output = cxx2flow_lib::metrics::to_text(&[(case.function.clone(), report)]);
+    insta::assert_snapshot!(format!("metrics__{}", case.name), output);
+    Ok(())
+}
+
+fn run_metrics_all_case(case: FixtureCase) -> Result<(), Failed> {
+    let report = metrics_all(&case.source, "metrics.cpp")
+        .unwrap_or_else(|error| panic!("failed to compute metrics {}: {error:?}", case.name));
+    let output = cxx2flow_lib::metrics::to_json(&report)
+        .unwrap_or_else(|error| panic!("failed to serialize metrics {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("metrics_all__{}", case.name), output);
+    Ok(())
+}
+
+fn run_structogram_case(case: FixtureCase) -> Result<(), Failed> {
+    let output = generate_structogram(
+        &case.source,
+        "structogram.cpp",
+        Some(case.function.clone()),
+        &RenderOptions::default(),
+    )
+    .unwrap_or_else(|error| panic!("failed to draw structogram {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("structogram__{}", case.name), output);
+    Ok(())
+}
+
+fn run_structogram_goto_case(case: FixtureCase) -> Result<(), Failed> {
+    let error = generate_structogram(
+        &case.source,
+        "structogram.c",
+        Some(case.function.clone()),
+        &RenderOptions::default(),
+    )
+    .expect_err("goto should not be drawn in a structogram");
+    insta::assert_snapshot!(
+        format!("structogram_error__{}", case.name),
+        format!("{error:?}")
+    );
+    Ok(())
+}
+
+fn run_lambda_case(case: FixtureCase, lambdas: LambdaMode) -> Result<(), Failed> {
+    let (prefix, backend): (&str, GraphDisplayBackend) = match lambdas {
+        LambdaMode::Separate => ("lambdas_separate", Mermaid::new().into()),
+        _ => ("lambdas_nested", Dot::new(false).into()),
+    };
+    let output = generate_with_options(
+        &case.source,
+        "lambdas.cpp",
+        Some(case.function.clone()),
+        backend,
+        &GraphOptions {
+            lambdas,
+            ..Default::default()
+        },
+    )
+    .unwrap_or_else(|error| panic!("failed to render lambdas {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    Ok(())
+}
+
+fn run_unreachable_case(case: FixtureCase) -> Result<(), Failed> {
+    let function = Some(case.function.clone());
+    let warning = unreachable_code(&case.source, "unreachable.cpp", function.clone())
+        .unwrap_or_else(|error| panic!("failed to check case {}: {error:?}", case.name));
+    let mut report = String::new();
+    if let Some(warning) = warning {
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut report, &warning)
+            .unwrap_or_else(|error| panic!("failed to render warning {}: {error}", case.name));
+    }
+    insta::assert_snapshot!(format!("unreachable_warning__{}", case.name), report);
+    for (prefix, unreachable, backend) in [
+        (
+            "unreachable_highlight",
+            UnreachableMode::Highlight,
+            GraphDisplayBackend::from(Dot::new(false)),
+        ),
+        (
+            "unreachable_highlight_mermaid",
+            UnreachableMode::Highlight,
+            Mermaid::new().into(),
+        ),
+        (
+            "unreachable_omit",
+            UnreachableMode::Omit,
+            Dot::new(false).into(),
+        ),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "unreachable.cpp",
+            function.clone(),
+            backend,
+            &GraphOptions {
+                unreachable,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_basic_block_case(case: FixtureCase) -> Result<(), Failed> {
+    for (prefix, max_block_lines, backend) in [
+        (
+            "basic_blocks",
+            None,
+            GraphDisplayBackend::from(Dot::new(false)),
+        ),
+        ("basic_blocks_max_2", Some(2), Dot::new(false).into()),
+        ("basic_blocks_tikz", None, Tikz::new().into()),
+        ("basic_blocks_svg", None, Svg::new().into()),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "blocks.cpp",
+            Some(case.function.clone()),
+            backend,
+            &GraphOptions {
+                basic_blocks: true,
+                max_block_lines,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_fold_case(case: FixtureCase) -> Result<(), Failed> {
+    for (prefix, max_depth, fold_charts, backend) in [
+        (
+            "fold_depth_1",
+            1,
+            false,
+            GraphDisplayBackend::from(Dot::new(false)),
+        ),
+        ("fold_depth_0_d2", 0, false, D2::new().into()),
+        ("fold_charts", 1, true, Dot::new(false).into()),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "fold.cpp",
+            Some(case.function.clone()),
+            backend,
+            &GraphOptions {
+                max_depth: Some(max_depth),
+                fold_charts,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_prune_constexpr_case(case: FixtureCase) -> Result<(), Failed> {
+    let output = generate_with_options(
+        &case.source,
+        "constexpr.cpp",
+        Some(case.function.clone()),
+        Dot::new(false).into(),
+        &GraphOptions {
+            prune_constexpr: true,
+            ..Default::default()
+        },
+    )
+    .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("prune_constexpr__{}", case.name), output);
+    Ok(())
+}
+
+fn run_resume_case(case: FixtureCase) -> Result<(), Failed> {
+    for (prefix, backend) in [
+        ("resume_edges", GraphDisplayBackend::from(Dot::new(false))),
+        ("resume_edges_mermaid", Mermaid::new().into()),
+        ("resume_edges_svg", Svg::new().into()),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "coroutine.cpp",
+            Some(case.function.clone()),
+            backend,
+            &GraphOptions {
+                resume_edges: true,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_separate_returns_case(case: FixtureCase) -> Result<(), Failed> {
+    for (prefix, backend) in [
+        (
+            "separate_returns",
+            GraphDisplayBackend::from(Dot::new(false)),
+        ),
+        ("separate_returns_d2", D2::new().into()),
+        ("separate_returns_mermaid", Mermaid::new().into()),
+        ("separate_returns_tikz", Tikz::new().into()),
+        ("separate_returns_svg", Svg::new().into()),
+        ("separate_returns_json", Json::new().into()),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "returns.c",
+            Some(case.function.clone()),
+            backend,
+            &GraphOptions {
+                separate_returns: true,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_render_options_case(case: FixtureCase) -> Result<(), Failed> {
+    let custom = RenderOptions {
+        yes: "true".to_owned(),
+        no: "false_or_0".to_owned(),
+        begin: "Start #1".to_owned(),
+        end: "Stop".to_owned(),
+        question_mark: false,
+        ..Default::default()
+    };
+    for (prefix, backend) in [
+        (
+            "locale_zh",
+            GraphDisplayBackend::from(Dot::new(false).with_options(RenderOptions::new(Locale::Zh))),
+        ),
+        (
+            "custom_labels_mermaid",
+            Mermaid::new().with_options(custom.clone()).into(),
+        ),
+        (
+            "custom_labels_tikz",
+            Tikz::new().with_options(custom).into(),
+        ),
+    ] {
+        let output = generate(
+            &case.source,
+            "labels.cpp",
+            Some(case.function.clone()),
+            backend,
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    let output = generate_structogram(
+        &case.source,
+        "labels.cpp",
+        Some(case.function.clone()),
+        &RenderOptions::new(Locale::Zh),
+    )
+    .unwrap_or_else(|error| panic!("failed to draw structogram {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("locale_zh_structogram__{}", case.name), output);
+    Ok(())
+}
+
+fn run_multi_way_case(case: FixtureCase) -> Result<(), Failed> {
+    for (prefix, backend) in [
+        ("multi_way", GraphDisplayBackend::from(Dot::new(false))),
+        ("multi_way_mermaid", Mermaid::new().into()),
+        ("multi_way_json", Json::new().into()),
+    ] {
+        let output = generate_with_options(
+            &case.source,
+            "switch.c",
+            Some(case.function.clone()),
+            backend,
+            &GraphOptions {
+                switches: SwitchMode::MultiWay,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_theme_case(case: FixtureCase) -> Result<(), Failed> {
+    let dark = Theme::builtin("dark")
+        .expect("dark is a built-in theme")
+        .unwrap_or_else(|error| panic!("failed to read the dark theme: {error:?}"));
+    let custom = Theme::from_toml(CUSTOM_THEME, "custom.toml")
+        .unwrap_or_else(|error| panic!("failed to read the custom theme: {error:?}"));
+    for (name, theme) in [("dark", dark), ("custom", custom)] {
+        let options = RenderOptions {
+            theme,
+            ..Default::default()
+        };
+        for (backend_name, backend) in [
+            (
+                "dot",
+                GraphDisplayBackend::from(Dot::new(false).with_options(options.clone())),
+            ),
+            ("d2", D2::new().with_options(options.clone()).into()),
+            (
+                "mermaid",
+                Mermaid::new().with_options(options.clone()).into(),
+            ),
+            ("tikz", Tikz::new().with_options(options.clone()).into()),
+            ("svg", Svg::new().with_options(options.clone()).into()),
+        ] {
+            let output = generate(
+                &case.source,
+                "theme.cpp",
+                Some(case.function.clone()),
+                backend,
+            )
+            .unwrap_or_else(|error| panic!("failed to render case {}: {error:?}", case.name));
+            insta::assert_snapshot!(
+                format!("theme_{name}_{backend_name}__{}", case.name),
+                output
+            );
+        }
+        let output = generate_structogram(
+            &case.source,
+            "theme.cpp",
+            Some(case.function.clone()),
+            &options,
+        )
+        .unwrap_or_else(|error| panic!("failed to draw structogram {}: {error:?}", case.name));
+        insta::assert_snapshot!(format!("theme_{name}_structogram__{}", case.name), output);
+    }
+    Ok(())
+}
+
+fn run_invalid_theme_case() -> Result<(), Failed> {
+    let error = Theme::from_toml(INVALID_THEME, "invalid.toml")
+        .expect_err("a named color is not a valid theme color");
+    let mut report = String::new();
+    GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+        .render_report(&mut report, &error)
+        .unwrap_or_else(|error| panic!("failed to render the theme error: {error}"));
+    insta::assert_snapshot!("theme_invalid", report);
+    Ok(())
+}
+
+fn run_link_case(case: FixtureCase, d2: bool) -> Result<(), Failed> {
+    let (prefix, backend): (&str, GraphDisplayBackend) = if d2 {
+        ("link_d2", D2::new().with_link("flow.cpp").into())
+    } else {
+        ("link_dot", Dot::new(false).with_link("flow.cpp").into())
+    };
+    let output = generate(
+        &case.source,
+        "flow.cpp",
+        Some(case.function.clone()),
+        backend,
+    )
+    .unwrap_or_else(|error| panic!("failed to render links {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("{prefix}__{}", case.name), output);
+    Ok(())
+}
+
+fn run_config_case(case: ConfigCase) -> Result<(), Failed> {
+    let dir = config_fixtures_dir();
+    let input = dir.join(case.input);
+    let command_line = ["cxx2flow"]
+        .into_iter()
+        .chain(case.flags.iter().copied())
+        .chain([input.to_str().expect("fixture paths are UTF-8")]);
+    let matches = <Args as clap::CommandFactory>::command()
+        .try_get_matches_from(command_line)
+        .unwrap_or_else(|error| panic!("invalid command line {}: {error}", case.snapshot_name));
+    let args = <Args as clap::FromArgMatches>::from_arg_matches(&matches)
+        .unwrap_or_else(|error| panic!("invalid command line {}: {error}", case.snapshot_name));
+    let settings = Settings::new(args, matches)
+        .unwrap_or_else(|error| panic!("failed to load config {}: {error:?}", case.snapshot_name));
+    let args = settings
+        .args(Some(&input))
+        .unwrap_or_else(|error| panic!("failed to apply config {}: {error:?}", case.snapshot_name));
+    let root = dir.display().to_string();
+    insta::assert_snapshot!(
+        case.snapshot_name,
+        format!("{args:#?}").replace(&root, "<root>")
+    );
+    Ok(())
+}
+
+/// An option given without the one it needs, neither on the command line nor in the config
+fn run_missing_option_case() -> Result<(), Failed> {
+    let dir = config_fixtures_dir();
+    let input = dir.join("src").join("flow.c");
+    let command_line = [
+        "cxx2flow",
+        "--max-block-lines",
+        "4",
+        input.to_str().expect("fixture paths are UTF-8"),
+    ];
+    let matches = <Args as clap::CommandFactory>::command()
+        .try_get_matches_from(command_line)
+        .unwrap_or_else(|error| panic!("invalid command line: {error}"));
+    let args = <Args as clap::FromArgMatches>::from_arg_matches(&matches)
+        .unwrap_or_else(|error| panic!("invalid command line: {error}"));
+    let settings = Settings::new(args, matches)
+        .unwrap_or_else(|error| panic!("failed to load config: {error:?}"));
+    let error = settings
+        .args(Some(&input))
+        .expect_err("--max-block-lines needs --basic-blocks");
+    let mut report = String::new();
+    GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+        .render_report(&mut report, &error)
+        .unwrap_or_else(|error| panic!("failed to render the config error: {error}"));
+    insta::assert_snapshot!("config__missing_option", report);
+    Ok(())
+}
+
+fn run_invalid_config_case() -> Result<(), Failed> {
+    let dir = config_fixtures_dir();
+    let error = Config::load(&dir.join("invalid.toml"))
+        .expect_err("backend is not an option of the command line");
+    let mut report = String::new();
+    GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+        .with_width(200)
+        .render_report(&mut report, &error)
+        .unwrap_or_else(|error| panic!("failed to render the config error: {error}"));
+    insta::assert_snapshot!(
+        "config__invalid",
+        report.replace(&dir.display().to_string(), "<root>")
+    );
+    Ok(())
+}
+
+/// Drive the language server through a scripted session, recording every response
+/// Open `text` in a language server and run `script`, which sends requests with the given
+/// function. Returns the transcript of the requests.
+fn lsp_transcript(
+    name: &str,
+    text: String,
+    script: impl FnOnce(&mut dyn FnMut(&str, Value) -> Value),
+) -> String {
+    let (client, server) = Connection::memory();
+    let server = std::thread::spawn(move || lsp::serve(&server));
+    let mut transcript = Vec::new();
+    let mut id = 0;
+    let mut request = |method: &str, params: Value| {
+        id += 1;
+        let request = Request::new(RequestId::from(id), method.to_owned(), params);
+        client.sender.send(request.into()).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => {
+                let response = serde_json::to_value(response).unwrap();
+                transcript.push(json!({ "method": method, "response": response }));
+                response
+            }
+            message => panic!("unexpected message {message:?}"),
+        }
+    };
+    let notify = |method: &str, params: Value| {
+        let notification = Notification::new(method.to_owned(), params);
+        client.sender.send(notification.into()).unwrap();
+    };
+
+    request("initialize", json!({ "capabilities": {} }));
+    notify("initialized", json!({}));
+    notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": {
+            "uri": LSP_URI,
+            "languageId": "cpp",
+            "version": 1,
+            "text": text,
+        }}),
+    );
+    script(&mut request);
+    request("shutdown", Value::Null);
+    notify("exit", Value::Null);
+    server
+        .join()
+        .unwrap()
+        .unwrap_or_else(|error| panic!("language server failed on {name}: {error:?}"));
+    serde_json::to_string_pretty(&transcript).unwrap()
+}
+
+const LSP_URI: &str = "file:///project/flow.cpp";
+
+fn run_lsp_case(case: FixtureCase) -> Result<(), Failed> {
+    let uri = LSP_URI;
+    let transcript = lsp_transcript(
+        &case.name,
+        String::from_utf8(case.source.clone()).unwrap(),
+        |request| {
+            let lenses = request(
+                "textDocument/codeLens",
+                json!({ "textDocument": { "uri": uri } }),
+            );
+            request(
+                "cxx2flow/render",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 3, "character": 4 },
+                    "format": "json",
+                }),
+            );
+            let last_lens = lenses["result"].as_array().unwrap().last().unwrap().clone();
+            request(
+                "workspace/executeCommand",
+                json!({
+                    "command": last_lens["command"]["command"],
+                    "arguments": last_lens["command"]["arguments"],
+                }),
+            );
+            request(
+                "cxx2flow/render",
+                json!({ "textDocument": { "uri": uri }, "position": { "line": 9, "character": 0 } }),
+            );
+            request(
+                "cxx2flow/render",
+                json!({ "textDocument": { "uri": "file:///closed.cpp" }, "position": { "line": 0, "character": 0 } }),
+            );
+        },
+    );
+    insta::assert_snapshot!(format!("lsp__{}", case.name), transcript);
+    Ok(())
+}
+
+/// A function without a flow graph keeps its error to itself
+fn run_lsp_broken_case() -> Result<(), Failed> {
+    let uri = LSP_URI;
+    let text = "int f() {\n  break;\n}\nint main() {\n  return 0;\n}\n";
+    let transcript = lsp_transcript("broken", text.to_owned(), |request| {
+        request(
+            "textDocument/codeLens",
+            json!({ "textDocument": { "uri": uri } }),
+        );
+        for line in [1, 4] {
+            request(
+                "cxx2flow/render",
+                json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": 2 } }),
+            );
+        }
+    });
+    insta::assert_snapshot!("lsp__broken", transcript);
+    Ok(())
+}
+
+/// Outputs which already hold the chart are not written again, so their mtime is kept
+fn run_write_if_changed_case() -> Result<(), Failed> {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("write_if_changed.dot");
+    fs::write(&path, "digraph {}\n").unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|x| x.set_modified(modified))
+        .unwrap();
+    assert!(!watch::write_if_changed(&path, "digraph {}\n").unwrap());
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    assert!(watch::write_if_changed(&path, "digraph { a }\n").unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "digraph { a }\n");
+    assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    Ok(())
+}
+
+/// A failing run is reported, and the next change runs again
+fn run_watch_error_case() -> Result<(), Failed> {
+    let (sender, receiver) = mpsc::channel();
+    let input = PathBuf::from("flow.cpp");
+    let event = |path: &Path| {
+        notify::Event::new(notify::EventKind::Modify(notify::event::ModifyKind::Any))
+            .add_path(path.to_path_buf())
+    };
+    sender.send(Ok(event(Path::new("other.cpp")))).unwrap();
+    sender.send(Ok(event(&input))).unwrap();
+    drop(sender);
+    let mut runs = 0;
+    watch::run_on_changes(
+        &receiver,
+        |path| path == input,
+        || {
+            runs += 1;
+            if runs == 1 {
+                Err("broken input")
+            } else {
+                Ok(())
+            }
+        },
+    );
+    assert_eq!(runs, 2);
+    Ok(())
+}
+
+fn run_batch_case(name: &str, input: PathBuf) -> Result<(), Failed> {
+    let batch = Batch::new(&input)
+        .unwrap_or_else(|error| panic!("failed to collect sources of {name}: {error:?}"));
+    let listing = batch
+        .files
+        .iter()
+        .map(|file| {
+            format!(
+                "{} -> {}\n",
+                file.strip_prefix(&batch.root).unwrap().display(),
+                batch.output_dir(Path::new("out"), file).display()
+            )
+        })
+        .collect::<String>();
+    insta::assert_snapshot!(format!("batch__{name}"), listing);
+    Ok(())
+}
+
+fn run_compile_flags_case() -> Result<(), Failed> {
+    let input = batch_fixtures_dir()
+        .join("build")
+        .join("compile_commands.json");
+    let batch = Batch::new(&input).unwrap_or_else(|error| panic!("failed to load {error:?}"));
+    let root = batch.root.display().to_string();
+    let flags = batch
+        .commands
+        .iter()
+        .map(|command| {
+            let options =
+                PreprocessorOptions::from_arguments(&command.arguments(), &command.directory);
+            format!(
+                "{}: {}\n",
+                command.file.strip_prefix(&batch.root).unwrap().display(),
+                options
+                    .args(Some(&command.file))
+                    .join(" ")
+                    .replace(&root, "<root>")
+            )
+        })
+        .collect::<String>();
+    insta::assert_snapshot!("preprocess__compile_flags", flags);
+    Ok(())
+}
+
+fn run_line_markers_case(name: &str) -> Result<(), Failed> {
+    let output = fs::read_to_string(
+        project_root()
+            .join("tests/fixtures/preprocess")
+            .join(format!("{name}.i")),
+    )
+    .unwrap_or_else(|error| panic!("failed to read preprocessor output: {error}"));
+    let source = follow_line_markers(&output);
+    let chart = generate(
+        source.as_bytes(),
+        "src/main.c",
+        None,
+        Dot::new(false).with_link("src/main.c").into(),
+    )
+    .unwrap_or_else(|error| panic!("failed to render preprocessed source: {error:?}"));
+    insta::assert_snapshot!(
+        format!("preprocess__{name}"),
+        format!("{source}\n---\n{chart}")
+    );
+    Ok(())
+}
+
+fn run_conditional_case(name: &str, defines: &[&str]) -> Result<(), Failed> {
+    let content =
+        fs::read_to_string(project_root().join("tests/fixtures/conditional/feature_flags.cpp"))
+            .unwrap_or_else(|error| panic!("failed to read conditional fixture: {error}"));
+    let defines: Vec<String> = defines.iter().map(|x| x.to_string()).collect();
+    let source = blank_directives(&content, &Macros::new(&defines, &[]));
+    let chart = generate(
+        source.as_bytes(),
+        "feature_flags.cpp",
+        Some("handle".to_owned()),
+        Dot::new(false).into(),
+    )
+    .unwrap_or_else(|error| panic!("failed to render conditional fixture: {error:?}"));
+    insta::assert_snapshot!(
+        format!("conditional__{name}"),
+        format!("{source}\n---\n{chart}")
+    );
+    Ok(())
+}
+
+/// `__cplusplus` and `__STDC_VERSION__` follow `--std`, or else the extension of the file
+fn run_predefined_case() -> Result<(), Failed> {
+    let content = fs::read_to_string(project_root().join("tests/fixtures/conditional/version.cpp"))
+        .unwrap_or_else(|error| panic!("failed to read conditional fixture: {error}"));
+    let mut res = Vec::new();
+    for (std, file) in [
+        (None, "version.cpp"),
+        (Some("c++20"), "version.cpp"),
+        (Some("gnu++11"), "version.cpp"),
+        (None, "version.c"),
+        (Some("c89"), "version.c"),
+    ] {
+        let source = blank_directives(&content, &Macros::predefined(std, Some(Path::new(file))));
+        let kept: Vec<&str> = source
+            .lines()
+            .filter(|x| x.trim().starts_with("return"))
+            .map(str::trim)
+            .collect();
+        res.push(format!(
+            "{file} {}: {}",
+            std.unwrap_or("default"),
+            kept.join(" ")
+        ));
+    }
+    insta::assert_snapshot!("conditional__predefined", res.join("\n"));
+    Ok(())
+}
+
+fn run_list_case(case: FixtureCase) -> Result<(), Failed> {
+    let functions = list_functions(&case.source, "list.cpp")
+        .unwrap_or_else(|error| panic!("failed to list functions {}: {error:?}", case.name));
+    insta::assert_snapshot!(
+        format!("list__{}", case.name),
+        cxx2flow_lib::list::to_text(&functions)
+    );
+    let output = cxx2flow_lib::list::to_json(&functions)
+        .unwrap_or_else(|error| panic!("failed to serialize list {}: {error:?}", case.name));
+    insta::assert_snapshot!(format!("list_json__{}", case.name), output);
+    Ok(())
+}
+
+/// A function without a flow graph is listed with its error, next to the others
+fn run_list_broken_case() -> Result<(), Failed> {
+    let source = "int f() { break; }\nint main() { return 0; }\n";
+    let functions = list_functions(source.as_bytes(), "list.cpp")
+        .unwrap_or_else(|error| panic!("failed to list functions: {error:?}"));
+    insta::assert_snapshot!("list__broken", cxx2flow_lib::list::to_text(&functions));
+    let output = cxx2flow_lib::list::to_json(&functions)
+        .unwrap_or_else(|error| panic!("failed to serialize list: {error:?}"));
+    insta::assert_snapshot!("list_json__broken", output);
+    Ok(())
+}
+
+fn run_selection_case(case: SelectionCase) -> Result<(), Failed> {
+    let (content, file_name) = read_error_fixture(case.fixture_name);
+    let output = generate(
+        &content,
+        &file_name,
+        Some(case.function.to_owned()),
+        Dot::new(false).into(),
+    )
+    .unwrap_or_else(|error| panic!("failed to select {}: {error:?}", case.function));
+    insta::assert_snapshot!(case.snapshot_name, output);
+    Ok(())
+}
+
 fn run_error_case(case: ErrorCase) -> Result<(), Failed> {
     let (content, file_name) = read_error_fixture(case.fixture_name);
     let error = generate(
@@ -279,6 +1322,214 @@ fn build_trials() -> Vec<Trial> {
         }));
     }
 
+    for case_name in MERMAID_CASES {
+        if should_skip_case(case_name) {
+            continue;
+        }
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("mermaid::{}", case.name);
+        trials.push(Trial::test(name, move || {
+            run_snapshot_case(case, BackendKind::Mermaid)
+        }));
+    }
+
+    for case_name in JSON_CASES {
+        if should_skip_case(case_name) {
+            continue;
+        }
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("json::{}", case.name);
+        trials.push(Trial::test(name, move || {
+            run_snapshot_case(case, BackendKind::Json)
+        }));
+    }
+
+    for case_name in SVG_CASES {
+        if should_skip_case(case_name) {
+            continue;
+        }
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("svg::{}", case.name);
+        trials.push(Trial::test(name, move || {
+            run_snapshot_case(case, BackendKind::Svg)
+        }));
+    }
+
+    for case_name in COMBINED_CASES {
+        for (label, backend) in [
+            ("dot_polyline", BackendKind::DotPolyline),
+            ("d2", BackendKind::D2),
+            ("tikz", BackendKind::Tikz),
+            ("mermaid", BackendKind::Mermaid),
+            ("json", BackendKind::Json),
+            ("svg", BackendKind::Svg),
+        ] {
+            let case = find_case(&cases, case_name).clone();
+            let name = format!("combined_{label}::{}", case.name);
+            trials.push(Trial::test(name, move || run_combined_case(case, backend)));
+        }
+    }
+
+    for case_name in METRICS_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("metrics::{}", case.name);
+        trials.push(Trial::test(name, move || run_metrics_case(case)));
+    }
+
+    for case_name in COMBINED_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("metrics_all::{}", case.name);
+        trials.push(Trial::test(name, move || run_metrics_all_case(case)));
+    }
+
+    for case_name in STRUCTOGRAM_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("structogram::{}", case.name);
+        trials.push(Trial::test(name, move || run_structogram_case(case)));
+    }
+
+    let case = find_case(&cases, "goto_backward_label").clone();
+    trials.push(Trial::test(
+        "structogram_error::goto_backward_label",
+        move || run_structogram_goto_case(case),
+    ));
+
+    for case_name in LAMBDA_CASES {
+        for lambdas in [LambdaMode::Separate, LambdaMode::Nested] {
+            let case = find_case(&cases, case_name).clone();
+            let name = format!("lambdas_{lambdas:?}::{}", case.name).to_lowercase();
+            trials.push(Trial::test(name, move || run_lambda_case(case, lambdas)));
+        }
+    }
+
+    for case_name in LINK_CASES {
+        for d2 in [false, true] {
+            let case = find_case(&cases, case_name).clone();
+            let backend = if d2 { "d2" } else { "dot" };
+            let name = format!("link_{backend}::{}", case.name);
+            trials.push(Trial::test(name, move || run_link_case(case, d2)));
+        }
+    }
+
+    let case = find_case(&cases, "class_method_and_main").clone();
+    for case_name in UNREACHABLE_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("unreachable::{}", case.name);
+        trials.push(Trial::test(name, move || run_unreachable_case(case)));
+    }
+
+    for case_name in BASIC_BLOCK_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("basic_blocks::{}", case.name);
+        trials.push(Trial::test(name, move || run_basic_block_case(case)));
+    }
+
+    let constexpr_case = find_case(&cases, "compile_time_and_init").clone();
+    trials.push(Trial::test(
+        "prune_constexpr::compile_time_and_init",
+        move || run_prune_constexpr_case(constexpr_case),
+    ));
+
+    let coroutine_case = find_case(&cases, "coroutine_suspend").clone();
+    trials.push(Trial::test("resume_edges::coroutine_suspend", move || {
+        run_resume_case(coroutine_case)
+    }));
+
+    let returns_case = find_case(&cases, "early_returns").clone();
+    trials.push(Trial::test("separate_returns::early_returns", move || {
+        run_separate_returns_case(returns_case)
+    }));
+
+    for case_name in FOLD_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("fold::{}", case.name);
+        trials.push(Trial::test(name, move || run_fold_case(case)));
+    }
+
+    for case_name in RENDER_OPTIONS_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("render_options::{}", case.name);
+        trials.push(Trial::test(name, move || run_render_options_case(case)));
+    }
+
+    for case_name in MULTI_WAY_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("multi_way::{}", case.name);
+        trials.push(Trial::test(name, move || run_multi_way_case(case)));
+    }
+
+    for case_name in THEME_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("theme::{}", case.name);
+        trials.push(Trial::test(name, move || run_theme_case(case)));
+    }
+    trials.push(Trial::test("theme::invalid", run_invalid_theme_case));
+
+    for case in CONFIG_CASES {
+        let name = format!("config::{}", case.snapshot_name);
+        trials.push(Trial::test(name, move || run_config_case(*case)));
+    }
+    trials.push(Trial::test("config::invalid", run_invalid_config_case));
+    trials.push(Trial::test(
+        "config::missing_option",
+        run_missing_option_case,
+    ));
+
+    trials.push(Trial::test("lsp::class_method_and_main", move || {
+        run_lsp_case(case)
+    }));
+    trials.push(Trial::test("lsp::broken", run_lsp_broken_case));
+    trials.push(Trial::test(
+        "watch::write_if_changed",
+        run_write_if_changed_case,
+    ));
+    trials.push(Trial::test("watch::error", run_watch_error_case));
+
+    let input = batch_fixtures_dir().join("src");
+    trials.push(Trial::test("batch::directory", move || {
+        run_batch_case("directory", input)
+    }));
+    let input = batch_fixtures_dir()
+        .join("build")
+        .join("compile_commands.json");
+    trials.push(Trial::test("batch::compile_commands", move || {
+        run_batch_case("compile_commands", input)
+    }));
+
+    trials.push(Trial::test(
+        "preprocess::compile_flags",
+        run_compile_flags_case,
+    ));
+    for name in ["line_markers", "line_markers_back"] {
+        trials.push(Trial::test(format!("preprocess::{name}"), move || {
+            run_line_markers_case(name)
+        }));
+    }
+    trials.push(Trial::test("conditional::undefined", || {
+        run_conditional_case("undefined", &[])
+    }));
+    trials.push(Trial::test("conditional::defined", || {
+        run_conditional_case(
+            "defined",
+            &["FEATURE_CACHE", "FEATURE_RETRY", "RETRIES=3", "NDEBUG"],
+        )
+    }));
+
+    trials.push(Trial::test("conditional::predefined", run_predefined_case));
+
+    for case_name in LIST_CASES {
+        let case = find_case(&cases, case_name).clone();
+        let name = format!("list::{}", case.name);
+        trials.push(Trial::test(name, move || run_list_case(case)));
+    }
+    trials.push(Trial::test("list::broken", run_list_broken_case));
+
+    for case in SELECTION_CASES {
+        let case = *case;
+        let name = format!("select::{}", case.snapshot_name);
+        trials.push(Trial::test(name, move || run_selection_case(case)));
+    }
+
     for case in ERROR_CASES {
         let case = *case;
         let name = format!("error::{}", case.snapshot_name);
@@ -12,6 +12,13 @@ type Cxx2flowExports = {
     functionLen: number,
     curly: number,
   ) => number
+  cxx2flow_generate_svg: (
+    contentPtr: number,
+    contentLen: number,
+    functionPtr: number,
+    functionLen: number,
+  ) => number
+  cxx2flow_list_functions: (contentPtr: number, contentLen: number) => number
   cxx2flow_result_ptr: () => number
   cxx2flow_result_len: () => number
   cxx2flow_error_ptr: () => number
@@ -98,6 +105,66 @@ export async function initWasm() {
 }
 
 export function generate_dot(content: string, functionName?: string, curly = false) {
+  return generate(content, functionName, 'cxx2flow_generate_dot', (exports, ...args) =>
+    exports.cxx2flow_generate_dot(...args, curly ? 1 : 0),
+  )
+}
+
+export function generate_svg(content: string, functionName?: string) {
+  return generate(content, functionName, 'cxx2flow_generate_svg', (exports, ...args) =>
+    exports.cxx2flow_generate_svg(...args),
+  )
+}
+
+export type FunctionInfo = {
+  name: string
+  qualified_name: string
+  signature: string
+  start_line: number
+  end_line: number
+  cyclomatic_complexity: number | null
+  error?: string
+}
+
+export function list_functions(content: string): FunctionInfo[] {
+  if (!wasm) {
+    throw new Error('cxx2flow wasm is not initialized. Call initWasm() first.')
+  }
+
+  let contentPtr = 0
+  let contentLen = 0
+
+  try {
+    ;[contentPtr, contentLen] = writeString(content ?? '')
+
+    const status = wasm.cxx2flow_list_functions(contentPtr, contentLen)
+
+    if (status === 0) {
+      const result = getString(wasm.cxx2flow_result_ptr(), wasm.cxx2flow_result_len())
+      return JSON.parse(result).functions
+    }
+
+    const message = getString(wasm.cxx2flow_error_ptr(), wasm.cxx2flow_error_len())
+    throw new Error(message || `cxx2flow_list_functions failed with status ${status}`)
+  } finally {
+    if (contentLen > 0) {
+      wasm.cxx2flow_dealloc(contentPtr, contentLen)
+    }
+  }
+}
+
+function generate(
+  content: string,
+  functionName: string | undefined,
+  entry: string,
+  call: (
+    exports: Cxx2flowExports,
+    contentPtr: number,
+    contentLen: number,
+    functionPtr: number,
+    functionLen: number,
+  ) => number,
+) {
   if (!wasm) {
     throw new Error('cxx2flow wasm is not initialized. Call initWasm() first.')
   }
@@ -114,20 +181,14 @@ export function generate_dot(content: string, functionName?: string, curly = fal
     ;[contentPtr, contentLen] = writeString(normalizedContent)
     ;[functionPtr, functionLen] = writeString(normalizedFunction)
 
-    const status = wasm.cxx2flow_generate_dot(
-      contentPtr,
-      contentLen,
-      functionPtr,
-      functionLen,
-      curly ? 1 : 0,
-    )
+    const status = call(wasm, contentPtr, contentLen, functionPtr, functionLen)
 
     if (status === 0) {
       return getString(wasm.cxx2flow_result_ptr(), wasm.cxx2flow_result_len())
     }
 
     const message = getString(wasm.cxx2flow_error_ptr(), wasm.cxx2flow_error_len())
-    throw new Error(message || `cxx2flow_generate_dot failed with status ${status}`)
+    throw new Error(message || `${entry} failed with status ${status}`)
   } finally {
     if (contentLen > 0) {
       wasm.cxx2flow_dealloc(contentPtr, contentLen)